@@ -1 +1,3 @@
 pub mod camera;
+pub mod framebuffer;
+pub mod integrator;
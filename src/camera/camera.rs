@@ -2,6 +2,7 @@ use std::{
     cell::RefCell,
     f64::consts::PI,
     io::{self, Write},
+    path::Path,
 };
 
 use rand::{
@@ -13,14 +14,20 @@ use rand::{
 use rand_xoshiro::{rand_core::SeedableRng, Xoshiro256PlusPlus};
 
 use crate::{
-    core::{point3::Point, ray::Ray, rgb::ARgb},
+    core::{point3::Point, ray::Ray},
     scene::hittable::Scene,
-    utils::{interval::Interval, math::f64_to_u32},
+    utils::math::f64_to_u32,
+};
+
+use super::{
+    framebuffer::Framebuffer,
+    integrator::{Integrator, Sample},
 };
 
 pub enum RenderError {
     WriteHeader(io::Error),
     WritePx(io::Error),
+    SavePng(image::ImageError),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -117,9 +124,12 @@ pub struct Camera {
     defocus: Defocuser,
     max_bounce_depth: u32,
     shatter: Shatter,
+    integrator: Integrator,
 }
 
 impl Camera {
+    // fails if antialiasing can't be set up, panics if image height doesn't fit u32
+    #[allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
     pub fn build(
         lookfrom: Option<Point>,
         lookat: Option<Point>,
@@ -202,9 +212,16 @@ impl Camera {
             defocus,
             max_bounce_depth,
             shatter,
+            integrator: Integrator::default(),
         })
     }
 
+    #[must_use]
+    pub fn with_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = integrator;
+        self
+    }
+
     // ray for pixel width number and height number
     fn ray_for(&self, wn: f64, hn: f64) -> Ray {
         // construct from the defocus disk and direct at randomly sampled point arount pixel
@@ -229,33 +246,62 @@ impl Camera {
         Ray::new(ray_orig, ray_dir, Some(ray_tm))
     }
 
+    // P3 to stdout
+    #[allow(clippy::missing_errors_doc)]
     pub fn render(&self, scene: &Scene) -> Result<(), RenderError> {
-        io::stdout()
+        let frame = self.render_frame(scene);
+        let mut stdout = io::stdout().lock();
+        stdout
             .write_all(format!("P3\n{} {}\n255\n", self.img_width, self.img_height).as_bytes())
             .map_err(RenderError::WriteHeader)?;
+        // no alpha in P3, the sky is composited back where the render is transparent
+        for px in frame.pixels() {
+            (px.color + px.background)
+                .write(&mut stdout)
+                .map_err(RenderError::WritePx)?;
+        }
+        Ok(())
+    }
+
+    // rgba png, for compositing over a background plate
+    #[allow(clippy::missing_errors_doc)]
+    pub fn render_png<T: AsRef<Path>>(&self, scene: &Scene, path: T) -> Result<(), RenderError> {
+        self.render_frame(scene)
+            .save_png(path)
+            .map_err(RenderError::SavePng)
+    }
 
+    pub fn render_frame(&self, scene: &Scene) -> Framebuffer {
+        let mut frame = Framebuffer::new(self.img_width, self.img_height);
         for hn in 0..self.img_height {
             for wn in 0..self.img_width {
                 let max_depth = self.max_bounce_depth;
-                let mut px_color = ARgb::default();
-                if let Some(ref anti_aliaser) = &self.anti_aliaser {
+                let px = if let Some(ref anti_aliaser) = &self.anti_aliaser {
+                    let mut sum = Sample::default();
                     for _ in 0..anti_aliaser.samples_per_pixel {
                         let r = self.ray_for(f64::from(wn), f64::from(hn));
-                        px_color = px_color + color(&r, scene, max_depth);
+                        let sample = self.integrator.radiance(&r, scene, max_depth);
+                        sum.color = sum.color + sample.color;
+                        sum.alpha += sample.alpha;
+                        sum.background = sum.background + sample.background;
+                    }
+                    Sample {
+                        color: sum.color * anti_aliaser.samples_scale,
+                        alpha: sum.alpha * anti_aliaser.samples_scale,
+                        background: sum.background * anti_aliaser.samples_scale,
                     }
-                    px_color = px_color * anti_aliaser.samples_scale;
                 } else {
                     let px_center = self.vp_upper_left
                         + (self.px_du * f64::from(wn))
                         + (self.px_dv * f64::from(hn));
                     let ray_dir = px_center - self.lookfrom;
                     let ray = Ray::new(self.lookfrom, ray_dir, None);
-                    px_color = color(&ray, scene, max_depth);
-                }
-                px_color.write(io::stdout()).map_err(RenderError::WritePx)?;
+                    self.integrator.radiance(&ray, scene, max_depth)
+                };
+                frame.push(px);
             }
         }
-        Ok(())
+        frame
     }
 
     fn defocus_disk_sample(&self, rng: &mut impl Rng) -> Point {
@@ -264,23 +310,59 @@ impl Camera {
     }
 }
 
-// it probably should be a scene method
-fn color(ray: &Ray, scene: &Scene, depth: u32) -> ARgb {
-    if depth == 0 {
-        return ARgb::new(0.0, 0.0, 0.0);
-    }
-
-    if let Some(ref mut rec) = scene.hit(ray, &Interval::new(0.001, f64::INFINITY)) {
-        let attenuation = &mut ARgb::default();
-        let scattered = &mut Ray::default();
-        if rec.mat.scatter(ray, attenuation, scattered, rec) {
-            *attenuation * color(scattered, scene, depth - 1)
-        } else {
-            ARgb::default()
-        }
-    } else {
-        let unit_dir = ray.dir().unit();
-        let a = 0.5 * (unit_dir.y() + 1.0);
-        ARgb::new(1.0, 1.0, 1.0) * (1.0 - a) + ARgb::new(0.5, 0.7, 1.0) * a
-    }
+#[test]
+fn test_pixel_on_the_sky_edge_keeps_sky_out_of_the_colour() {
+    use std::sync::Arc;
+
+    use crate::{
+        core::rgb::ARgb,
+        scene::{hittable::Hittable, material::DiffuseLight, quad::Quad},
+    };
+
+    // single pixel, the left half of it is covered by a red emitter, the rest is sky
+    let camera = Camera::build(
+        None,
+        None,
+        None,
+        1,
+        1.0,
+        Some(2_000),
+        None,
+        None,
+        None,
+        Some(2),
+    )
+    .unwrap_or_else(|_| panic!("valid camera"));
+    let mut scene = Scene::default();
+    let red: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new(-5.0, -5.0, -1.0),
+        Point::new(5.0, 0.0, 0.0),
+        Point::new(0.0, 10.0, 0.0),
+        Arc::new(DiffuseLight::new(ARgb::new(1.0, 0.0, 0.0))),
+    ));
+    scene.add(&red);
+    scene.build_bvh();
+
+    let frame = camera.render_frame(&scene);
+    let px = *frame.pixel(0, 0).expect("one pixel");
+    assert!((px.alpha - 0.5).abs() < 0.04, "{px:?}");
+    // premultiplied: only the covered half contributes to colour, the sky stays apart
+    let [r, g, b] = px.color.channels();
+    assert_approx_eq::assert_approx_eq!(r, px.alpha);
+    assert_approx_eq::assert_approx_eq!(g + b, 0.0);
+    let [sr, sg, sb] = px.background.channels();
+    assert!(sb > sg && sg > sr && sr > 0.3, "{px:?}");
+
+    let path = std::env::temp_dir().join(format!("sky_edge_{}.png", std::process::id()));
+    frame
+        .save_png(&path)
+        .unwrap_or_else(|e| panic!("png is written: {e}"));
+    let png = image::open(&path)
+        .unwrap_or_else(|e| panic!("png is read back: {e}"))
+        .to_rgba8();
+    std::fs::remove_file(&path).ok();
+    // pure red edge, neither brighter than the emitter nor fringed by the sky
+    let image::Rgba([r, g, b, a]) = *png.get_pixel(0, 0);
+    assert_eq!([r, g, b], [255, 0, 0]);
+    assert!((118..=138).contains(&a), "alpha {a}");
 }
@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use image::{ImageResult, Rgba, RgbaImage};
+
+use super::integrator::Sample;
+
+// Rendered image with coverage, pixels in rows from the top left
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Sample>,
+}
+
+impl Framebuffer {
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: Vec::with_capacity(width as usize * height as usize),
+        }
+    }
+
+    // pixels are filled in row order
+    pub fn push(&mut self, sample: Sample) {
+        self.pixels.push(sample);
+    }
+
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32) -> Option<&Sample> {
+        if x >= self.width {
+            return None;
+        }
+        self.pixels
+            .get(y as usize * self.width as usize + x as usize)
+    }
+
+    #[must_use]
+    pub fn pixels(&self) -> &[Sample] {
+        &self.pixels
+    }
+
+    // png stores straight alpha, light added where nothing is covered (reflections on
+    // a shadow catcher with alpha 0) can't be represented and is lost
+    #[allow(clippy::missing_errors_doc)]
+    pub fn save_png<T: AsRef<Path>>(&self, path: T) -> ImageResult<()> {
+        let image = RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let sample = self.pixel(x, y).copied().unwrap_or_default();
+            let alpha = sample.alpha.clamp(0.0, 1.0);
+            let straight = if alpha > 0.0 {
+                sample.color / alpha
+            } else {
+                sample.color
+            };
+            let [r, g, b] = straight.to_rgb8();
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let a = (alpha * 255.0).round() as u8;
+            Rgba([r, g, b, a])
+        });
+        image.save(path)
+    }
+}
@@ -0,0 +1,467 @@
+use crate::{
+    core::{point3::Point, ray::Ray, rgb::ARgb},
+    scene::{
+        hittable::{HitRec, Scene},
+        material::Matte,
+    },
+    utils::interval::Interval,
+};
+
+// offset from surface for secondary rays, avoids self intersection (shadow acne)
+const RAY_EPSILON: f64 = 0.001;
+
+// Integrator decides what is computed for every camera ray
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Integrator {
+    // full light transport, recursive bounces through materials
+    #[default]
+    PathTracer,
+    // geometry look-dev: fraction of cosine-weighted hemisphere rays from the first hit
+    // which are not blocked within max_dist, rendered as grey
+    AmbientOcclusion {
+        samples: u32,
+        max_dist: f64,
+    },
+}
+
+// Colour of a camera ray and its coverage: alpha is 0 where the background (or a holdout)
+// is seen, so renders can be composited over a plate. Colour is premultiplied, shadow
+// catchers add their reflections on top of the darkened plate:
+// composite = plate * (1 - alpha) + color
+// Alpha is the part of the ray which reaches the background unscattered, fog in front of
+// the sky covers it by how much of the ray it stops. The sky itself is kept apart in
+// background, for outputs without alpha which show it instead of a plate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sample {
+    pub color: ARgb,
+    pub alpha: f64,
+    pub background: ARgb,
+}
+
+impl Sample {
+    fn opaque(color: ARgb) -> Self {
+        Self {
+            color,
+            alpha: 1.0,
+            background: ARgb::default(),
+        }
+    }
+
+    // uncovered part of the sample is only uncovered by the part of weight getting through
+    fn seen_through(self, weight: ARgb) -> Self {
+        Self {
+            color: weight * self.color,
+            alpha: 1.0 - weight.luminance() * (1.0 - self.alpha),
+            background: weight * self.background,
+        }
+    }
+
+    fn sky(ray: &Ray) -> Self {
+        Self {
+            color: ARgb::default(),
+            alpha: 0.0,
+            background: background(ray),
+        }
+    }
+}
+
+impl Integrator {
+    #[must_use]
+    pub fn radiance(&self, ray: &Ray, scene: &Scene, depth: u32) -> Sample {
+        match *self {
+            Integrator::PathTracer => path_sample(ray, scene, depth),
+            Integrator::AmbientOcclusion { samples, max_dist } => {
+                ambient_occlusion(ray, scene, samples, max_dist)
+            }
+        }
+    }
+}
+
+// matte materials only matter where the camera sees them directly,
+// further bounces treat them as ordinary surfaces. Transparent ones let the camera ray
+// go on, what it sees behind them is seen through the product of their weights.
+fn path_sample(ray: &Ray, scene: &Scene, depth: u32) -> Sample {
+    let mut ray = ray.clone();
+    let mut weight = ARgb::new(1.0, 1.0, 1.0);
+    for depth in (1..=depth).rev() {
+        let Some(rec) = scene.hit(&ray, &Interval::new(RAY_EPSILON, f64::INFINITY)) else {
+            return Sample::sky(&ray).seen_through(weight);
+        };
+        weight = weight * ray.transmittance(rec.t);
+        let sample = match rec.mat.matte() {
+            None => Sample::opaque(shade(&ray, scene, &rec, depth, true)),
+            Some(Matte::Holdout) => Sample::default(),
+            Some(Matte::ShadowCatcher) => shadow_catcher(&ray, scene, &rec, depth),
+            Some(Matte::Transparent) => {
+                let attenuation = &mut ARgb::default();
+                let scattered = &mut Ray::default();
+                if !rec.mat.scatter(&ray, attenuation, scattered, &rec) {
+                    return Sample::opaque(ARgb::default());
+                }
+                weight = weight * *attenuation;
+                ray = std::mem::take(scattered);
+                continue;
+            }
+        };
+        return sample.seen_through(weight);
+    }
+    Sample::opaque(ARgb::default())
+}
+
+// count_sampled_emission is false after bounces which already sampled scene lights
+// explicitly, so hitting the same emitter again is not counted twice
+fn color(ray: &Ray, scene: &Scene, depth: u32, count_sampled_emission: bool) -> ARgb {
+    if depth == 0 {
+        return ARgb::new(0.0, 0.0, 0.0);
+    }
+
+    // closed media are left through their surfaces, rays escaping to the sky aren't in any
+    match scene.hit(ray, &Interval::new(RAY_EPSILON, f64::INFINITY)) {
+        Some(rec) => {
+            ray.transmittance(rec.t) * shade(ray, scene, &rec, depth, count_sampled_emission)
+        }
+        None => background(ray),
+    }
+}
+
+fn shade(ray: &Ray, scene: &Scene, rec: &HitRec, depth: u32, count_sampled_emission: bool) -> ARgb {
+    let emitted = if count_sampled_emission || !rec.mat.is_sampled_light() {
+        rec.mat.emitted(rec.tx_coord.u, rec.tx_coord.v, &rec.p)
+    } else {
+        ARgb::default()
+    };
+    let direct = direct_light(ray, scene, rec);
+    let attenuation = &mut ARgb::default();
+    let scattered = &mut Ray::default();
+    if rec.mat.scatter(ray, attenuation, scattered, rec) {
+        // rays going straight through still come from the last vertex which sampled lights
+        let count_next = match rec.mat.matte() {
+            Some(Matte::Transparent) => count_sampled_emission,
+            _ => rec.mat.eval(ray, &scattered.dir().unit(), rec).is_none(),
+        };
+        emitted + direct + *attenuation * color(scattered, scene, depth - 1, count_next)
+    } else {
+        emitted + direct
+    }
+}
+
+fn background(ray: &Ray) -> ARgb {
+    let unit_dir = ray.dir().unit();
+    let a = 0.5 * (unit_dir.y() + 1.0);
+    ARgb::new(1.0, 1.0, 1.0) * (1.0 - a) + ARgb::new(0.5, 0.7, 1.0) * a
+}
+
+// Light reaching the catcher compared with the light it would get without the rest of
+// the scene: lights shadowed or not, sky seen or hidden by an object. The ratio darkens
+// the plate through alpha, light added by objects (reflections, bounce) stays as colour.
+fn shadow_catcher(ray: &Ray, scene: &Scene, rec: &HitRec, depth: u32) -> Sample {
+    let (mut received, mut unblocked) = match light_sample(ray, scene, rec) {
+        Some((li, occluded)) => (if occluded { ARgb::default() } else { li }, li),
+        None => (ARgb::default(), ARgb::default()),
+    };
+    let attenuation = &mut ARgb::default();
+    let scattered = &mut Ray::default();
+    if rec.mat.scatter(ray, attenuation, scattered, rec) {
+        let lit = rec.mat.eval(ray, &scattered.dir().unit(), rec).is_some();
+        received = received + *attenuation * color(scattered, scene, depth - 1, !lit);
+        unblocked = unblocked + *attenuation * background(scattered);
+    }
+
+    let (received_y, unblocked_y) = (received.luminance(), unblocked.luminance());
+    let alpha = if unblocked_y > 0.0 {
+        1.0 - (received_y / unblocked_y).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let [r, g, b] = received.channels();
+    let [ur, ug, ub] = unblocked.channels();
+    Sample {
+        color: ARgb::new(r - ur, g - ug, b - ub).map(|c| c.max(0.0)),
+        alpha,
+        background: ARgb::default(),
+    }
+}
+
+// explicit sampling of one scene light with a shadow ray, the light is picked by the
+// light bvh and its contribution is divided by the probability of the pick
+fn direct_light(ray: &Ray, scene: &Scene, rec: &HitRec) -> ARgb {
+    match light_sample(ray, scene, rec) {
+        Some((li, false)) => li,
+        _ => ARgb::default(),
+    }
+}
+
+// contribution of a sampled light as if nothing blocked it, and whether something does
+fn light_sample(ray: &Ray, scene: &Scene, rec: &HitRec) -> Option<(ARgb, bool)> {
+    let mut rng = rand::rng();
+    let n = (!rec.mat.is_volumetric()).then_some(&rec.n);
+    let (light, pmf) = scene.sample_light(&rec.p, n, &mut rng)?;
+    let sample = light.sample_li(&rec.p, &mut rng)?;
+    let f = rec.mat.eval(ray, &sample.wi, rec)?;
+    // shadow rays go through the media of the shading point
+    let shadow_ray = ray.continued(rec.p, sample.wi);
+    let occluded = scene.occluded(
+        &shadow_ray,
+        &Interval::new(RAY_EPSILON, sample.dist - RAY_EPSILON),
+    );
+    let absorbed = shadow_ray.transmittance(sample.dist);
+    Some((f * sample.li * absorbed / pmf, occluded))
+}
+
+// the estimator is the plain visible fraction: cosine weighting is in the sampling,
+// so it cancels the cos term of the occlusion integral
+fn ambient_occlusion(ray: &Ray, scene: &Scene, samples: u32, max_dist: f64) -> Sample {
+    let Some(rec) = scene.hit(ray, &Interval::new(RAY_EPSILON, f64::INFINITY)) else {
+        return Sample {
+            color: ARgb::default(),
+            alpha: 0.0,
+            background: ARgb::new(1.0, 1.0, 1.0),
+        };
+    };
+    if samples == 0 {
+        return Sample::opaque(ARgb::new(1.0, 1.0, 1.0));
+    }
+
+    let mut rng = rand::rng();
+    let probe_t = Interval::new(RAY_EPSILON, max_dist);
+    let unoccluded = (0..samples)
+        .filter(|_| {
+            let dir = Point::random_cosine_on_hemisphere(&mut rng, &rec.n);
+            !scene.occluded(&Ray::new(rec.p, dir, Some(ray.time())), &probe_t)
+        })
+        .count();
+
+    #[allow(clippy::cast_precision_loss)]
+    let visibility = unoccluded as f64 / f64::from(samples);
+    Sample::opaque(ARgb::new(visibility, visibility, visibility))
+}
+
+#[test]
+fn test_shadow_catcher_is_transparent_except_in_shadow() {
+    use std::sync::Arc;
+
+    use crate::scene::{
+        hittable::Hittable,
+        material::{Holdout, Lambertian, Material, ShadowCatcher},
+        quad::Quad,
+        sphere::Sphere,
+    };
+
+    // ball resting on a catcher, lit by the sky only
+    let mut scene = Scene::default();
+    let ground: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new(-50.0, 0.0, 50.0),
+        Point::new(100.0, 0.0, 0.0),
+        Point::new(0.0, 0.0, -100.0),
+        Arc::new(ShadowCatcher::new(ARgb::new(0.5, 0.5, 0.5))),
+    ));
+    let ball_material: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.5, 0.5, 0.5), 1.0));
+    let ball: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1.0,
+        Point::new(0.0, 1.0, 0.0),
+        ball_material,
+    ));
+    let hole: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        0.5,
+        Point::new(-10.0, 0.5, 0.0),
+        Arc::new(Holdout),
+    ));
+    scene.add(&ground);
+    scene.add(&ball);
+    scene.add(&hole);
+    scene.build_bvh();
+
+    let integrator = Integrator::PathTracer;
+    let mean_alpha = |x: f64| {
+        let down = Ray::new(Point::new(x, 0.5, 0.0), Point::new(0.0, -1.0, 0.0), None);
+        let n = 2_000;
+        let sum: f64 = (0..n)
+            .map(|_| integrator.radiance(&down, &scene, 10).alpha)
+            .sum();
+        sum / f64::from(n)
+    };
+    // next to the ball a good part of the sky is hidden, far away nothing is
+    assert!(mean_alpha(1.2) > 0.1);
+    assert!(mean_alpha(30.0) < 0.02);
+
+    let up = Ray::new(Point::new(0.0, 0.5, 5.0), Point::new(0.0, 1.0, 0.0), None);
+    assert_approx_eq::assert_approx_eq!(integrator.radiance(&up, &scene, 10).alpha, 0.0);
+    let into_hole = Ray::new(
+        Point::new(-10.0, 0.5, 5.0),
+        Point::new(0.0, 0.0, -1.0),
+        None,
+    );
+    let sample = integrator.radiance(&into_hole, &scene, 10);
+    assert_approx_eq::assert_approx_eq!(sample.alpha, 0.0);
+    assert_approx_eq::assert_approx_eq!(sample.color.luminance(), 0.0);
+}
+
+#[test]
+fn test_ambient_occlusion_sees_half_the_sky_in_a_corner() {
+    use std::sync::Arc;
+
+    use crate::scene::{hittable::Hittable, material::Lambertian, quad::Quad};
+
+    // floor meeting a wall along the z axis
+    let grey = Arc::new(Lambertian::new(ARgb::new(0.5, 0.5, 0.5), 1.0));
+    let mut scene = Scene::default();
+    let floor: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new(-50.0, 0.0, 50.0),
+        Point::new(100.0, 0.0, 0.0),
+        Point::new(0.0, 0.0, -100.0),
+        grey.clone(),
+    ));
+    let wall: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new(0.0, 0.0, -50.0),
+        Point::new(0.0, 0.0, 100.0),
+        Point::new(0.0, 50.0, 0.0),
+        grey,
+    ));
+    scene.add(&floor);
+    scene.add(&wall);
+    scene.build_bvh();
+
+    let ao = |x: f64, max_dist: f64| {
+        let integrator = Integrator::AmbientOcclusion {
+            samples: 4_000,
+            max_dist,
+        };
+        let down = Ray::new(Point::new(x, 1.0, 0.0), Point::new(0.0, -1.0, 0.0), None);
+        integrator.radiance(&down, &scene, 1)
+    };
+    // at the foot of the wall it hides the half of the hemisphere behind it
+    let corner = ao(0.01, 100.0);
+    assert_approx_eq::assert_approx_eq!(corner.alpha, 1.0);
+    assert!((corner.color.luminance() - 0.5).abs() < 0.03, "{corner:?}");
+    // far from the wall, or with probes too short to reach it, nothing is occluded
+    assert_approx_eq::assert_approx_eq!(ao(40.0, 5.0).color.luminance(), 1.0);
+    assert_approx_eq::assert_approx_eq!(ao(0.01, 0.005).color.luminance(), 1.0);
+
+    // nothing hit, transparent
+    let sky = Integrator::AmbientOcclusion {
+        samples: 16,
+        max_dist: 1.0,
+    }
+    .radiance(
+        &Ray::new(Point::new(5.0, 1.0, 0.0), Point::new(0.0, 1.0, 0.0), None),
+        &scene,
+        1,
+    );
+    assert_approx_eq::assert_approx_eq!(sky.alpha, 0.0);
+}
+
+#[test]
+fn test_coloured_fog_covers_the_sky_by_its_transmittance() {
+    use std::sync::Arc;
+
+    use crate::scene::{
+        constant_medium::ConstantMedium, hittable::Hittable, material::Lambertian, medium::Medium,
+        phase::IsotropicPhase, sphere::Sphere,
+    };
+
+    // camera ray through the middle of a reddish fog ball, two units of it
+    let sigma_s = ARgb::new(0.1, 0.4, 0.8);
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1.0,
+        Point::new(0.0, 0.0, -3.0),
+        Arc::new(Lambertian::new(ARgb::default(), 1.0)),
+    ));
+    let fog: Arc<dyn Hittable> = Arc::new(ConstantMedium::with_medium(
+        boundary,
+        Medium::new(ARgb::default(), sigma_s, Arc::new(IsotropicPhase)),
+    ));
+    let mut scene = Scene::default();
+    scene.add(&fog);
+    scene.build_bvh();
+
+    let ray = Ray::new(Point::default(), Point::new(0.0, 0.0, -1.0), None);
+    let n = 40_000;
+    let (mut alpha, mut sky) = (0.0, ARgb::default());
+    for _ in 0..n {
+        let sample = Integrator::PathTracer.radiance(&ray, &scene, 10);
+        alpha += sample.alpha;
+        sky = sky + sample.background;
+    }
+    alpha /= f64::from(n);
+    sky = sky / f64::from(n);
+
+    let tr = sigma_s.map(|s| f64::exp(-2.0 * s));
+    assert!((alpha - (1.0 - tr.luminance())).abs() < 0.02, "{alpha}");
+    // sky straight behind the ball, seen through it
+    let expected = tr * background(&ray);
+    for (got, want) in sky.channels().iter().zip(expected.channels()) {
+        assert!((got - want).abs() < 0.02, "{sky:?} {expected:?}");
+    }
+}
+
+#[test]
+fn test_absorbing_medium_dims_objects_and_lights_inside_of_it() {
+    use std::sync::Arc;
+
+    use crate::scene::{
+        hittable::Hittable,
+        light::{Light, PointLight},
+        material::{Dielectric, DiffuseLight, Lambertian, Material},
+        quad::Quad,
+        sphere::Sphere,
+    };
+
+    // tinted water which doesn't refract, ball of radius 2 with a card in the middle
+    let absorption = ARgb::new(0.1, 0.3, 0.6);
+    let water: Arc<dyn Material> = Arc::new(Dielectric::with_absorption(1.0, absorption));
+    let ball: Arc<dyn Hittable> = Arc::new(Sphere::new_static(2.0, Point::default(), water));
+    let card = |mat: Arc<dyn Material>| -> Arc<dyn Hittable> {
+        Arc::new(Quad::new(
+            Point::new(-0.5, -0.5, 0.0),
+            Point::new(1.0, 0.0, 0.0),
+            Point::new(0.0, 1.0, 0.0),
+            mat,
+        ))
+    };
+    let ray = Ray::new(Point::new(0.0, 0.0, -5.0), Point::new(0.0, 0.0, 1.0), None);
+    let ray_t = Interval::new(RAY_EPSILON, f64::INFINITY);
+
+    // glowing card seen through two units of water
+    let mut scene = Scene::default();
+    scene.add(&ball);
+    scene.add(&card(Arc::new(DiffuseLight::new(ARgb::new(1.0, 1.0, 1.0)))));
+    scene.build_bvh();
+    let seen = Integrator::PathTracer.radiance(&ray, &scene, 10).color;
+    let expected = absorption.map(|a| f64::exp(-2.0 * a));
+    for (got, want) in seen.channels().iter().zip(expected.channels()) {
+        assert_approx_eq::assert_approx_eq!(got, want);
+    }
+
+    // grey card lit by a light one unit in front of it, also in the water
+    let mut scene = Scene::default();
+    scene.add(&ball);
+    scene.add(&card(Arc::new(Lambertian::new(
+        ARgb::new(0.5, 0.5, 0.5),
+        1.0,
+    ))));
+    let light: Arc<dyn Light> = Arc::new(PointLight::new(
+        Point::new(0.0, 0.0, -1.0),
+        ARgb::new(1.0, 1.0, 1.0),
+    ));
+    scene.add_light(&light);
+    scene.build_bvh();
+
+    let entry = scene.hit(&ray, &ray_t).expect("ray enters the water");
+    let (mut attenuation, mut inside) = (ARgb::default(), Ray::default());
+    assert!(entry
+        .mat
+        .scatter(&ray, &mut attenuation, &mut inside, &entry));
+    let rec = scene.hit(&inside, &ray_t).expect("ray reaches the card");
+    let (in_water, blocked) = light_sample(&inside, &scene, &rec).expect("card is lit");
+    assert!(!blocked);
+    let dry = Ray::new(inside.orig(), inside.dir(), None);
+    let (in_air, _) = light_sample(&dry, &scene, &rec).expect("card is lit");
+    for ((wet, dry), a) in in_water
+        .channels()
+        .iter()
+        .zip(in_air.channels())
+        .zip(absorption.channels())
+    {
+        assert_approx_eq::assert_approx_eq!(wet / dry, f64::exp(-a));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod onb;
 pub mod point3;
 pub mod ray;
 pub mod rgb;
@@ -0,0 +1,56 @@
+use super::point3::Point;
+
+// orthonormal basis around w, used to move between local shading space (w is "up")
+// and world space
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Point,
+    pub v: Point,
+    pub w: Point,
+}
+
+impl Onb {
+    // w is expected to be unit
+    #[must_use]
+    pub fn from_w(w: &Point) -> Self {
+        // any vector which is not parallel to w will do
+        let a = if w.x().abs() > 0.9 {
+            Point::new(0.0, 1.0, 0.0)
+        } else {
+            Point::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit();
+        let u = v.cross(w);
+        Onb { u, v, w: *w }
+    }
+
+    // u follows tangent projected to the plane perpendicular to w (e.g. dp/du of a surface),
+    // so anisotropic materials line up with texture directions
+    #[must_use]
+    pub fn from_w_tangent(w: &Point, tangent: &Point) -> Self {
+        let u = *tangent - *w * tangent.scalar_prod(w);
+        if u.near_zero() {
+            return Self::from_w(w);
+        }
+        let u = u.unit();
+        Onb {
+            u,
+            v: w.cross(&u),
+            w: *w,
+        }
+    }
+
+    #[must_use]
+    pub fn local_to_world(&self, local: &Point) -> Point {
+        self.u * local.x() + self.v * local.y() + self.w * local.z()
+    }
+
+    #[must_use]
+    pub fn world_to_local(&self, world: &Point) -> Point {
+        Point::new(
+            world.scalar_prod(&self.u),
+            world.scalar_prod(&self.v),
+            world.scalar_prod(&self.w),
+        )
+    }
+}
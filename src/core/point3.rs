@@ -1,6 +1,6 @@
 use std::{
     f64::consts::TAU,
-    ops::{Add, Div, Mul, Neg, RangeInclusive, Sub},
+    ops::{Add, Div, Mul, Neg, Sub},
 };
 
 use rand::{distr::StandardUniform, Rng};
@@ -16,31 +16,38 @@ pub struct Point {
 }
 
 impl Point {
+    #[must_use]
     pub fn new(x: f64, y: f64, z: f64) -> Self {
         Point { e: [x, y, z] }
     }
 
+    #[must_use]
     pub fn coord(&self, ax: Axis) -> f64 {
         self.e[std::convert::Into::<usize>::into(ax)]
     }
 
+    #[must_use]
     pub fn x(self) -> f64 {
         self.e[0]
     }
 
+    #[must_use]
     pub fn y(self) -> f64 {
         self.e[1]
     }
 
+    #[must_use]
     pub fn z(self) -> f64 {
         self.e[2]
     }
 
+    #[must_use]
     pub fn unit(&self) -> Self {
         *self / self.size()
     }
 
     // vector length
+    #[must_use]
     pub fn size(&self) -> f64 {
         f64::sqrt(self.squared_size())
     }
@@ -49,6 +56,7 @@ impl Point {
         self.e[0] * self.e[0] + self.e[1] * self.e[1] + self.e[2] * self.e[2]
     }
 
+    #[must_use]
     pub fn scalar_prod(&self, rhs: &Point) -> f64 {
         if self == rhs {
             self.squared_size()
@@ -57,6 +65,7 @@ impl Point {
         }
     }
 
+    #[must_use]
     pub fn cross(&self, rhs: &Point) -> Point {
         Point {
             e: [
@@ -67,29 +76,12 @@ impl Point {
         }
     }
 
-    fn random(rng: &mut impl Rng) -> Self {
-        let range = 0.0..1.0;
-        Point {
-            e: [
-                rng.random_range(range.clone()),
-                rng.random_range(range.clone()),
-                rng.random_range(range),
-            ],
-        }
-    }
-
-    fn random_with_interval(rng: &mut impl Rng, range: RangeInclusive<f64>) -> Self {
-        let x = rng.random_range(range.clone());
-        let y = rng.random_range(range.clone());
-        let z = rng.random_range(range);
-        Point { e: [x, y, z] }
-    }
-
-    pub fn random_unit_on_sphere(rng: &mut impl Rng) -> Self {
-        let x = rng.sample(StandardUniform);
-        let y = rng.sample(StandardUniform);
-        let z = rng.sample(StandardUniform);
-        Point::new(x, y, z).unit()
+    // uniform on the whole sphere: z is uniform in [-1, 1] (archimedes), phi in [0, 2pi)
+    pub fn random_unit_on_sphere(rng: &mut (impl Rng + ?Sized)) -> Self {
+        let z: f64 = 1.0 - 2.0 * rng.sample::<f64, _>(StandardUniform);
+        let phi = TAU * rng.sample::<f64, _>(StandardUniform);
+        let r = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+        Point::new(r * phi.cos(), r * phi.sin(), z)
     }
 
     // we can use outwarding normal - if scalar production is > 0
@@ -103,16 +95,29 @@ impl Point {
         }
     }
 
+    // normal plus a random unit vector is distributed as cos(theta) around the normal,
+    // which is the importance sampling we want for diffuse-like integrands
+    pub fn random_cosine_on_hemisphere(rng: &mut (impl Rng + ?Sized), n: &Point) -> Point {
+        let dir = *n + Point::random_unit_on_sphere(rng);
+        if dir.near_zero() {
+            *n
+        } else {
+            dir.unit()
+        }
+    }
+
     pub fn random_on_unit_disk(rng: &mut impl Rng) -> Self {
         let r = rng.random::<f64>().sqrt();
         let theta = rng.random_range(0.0..TAU);
         Point::new(r * theta.cos(), r * theta.sin(), 0.0)
     }
 
+    #[must_use]
     pub fn near_zero(&self) -> bool {
         self.e.iter().all(|x| x.abs() < ALMOST_ZERO)
     }
 
+    #[must_use]
     pub fn reflect(&self, n: &Point) -> Point {
         *self - *n * 2.0 * self.scalar_prod(n)
     }
@@ -167,3 +172,26 @@ impl Neg for Point {
         }
     }
 }
+
+#[test]
+fn test_random_unit_on_sphere_covers_every_octant() {
+    use rand::SeedableRng;
+
+    let mut rng = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(3);
+    let n = 80_000;
+    let mut octants = [0_u32; 8];
+    let mut mean = Point::default();
+    for _ in 0..n {
+        let p = Point::random_unit_on_sphere(&mut rng);
+        assert!((p.size() - 1.0).abs() < 1e-9);
+        let octant = p.e.iter().fold(0, |o, &c| o * 2 + usize::from(c < 0.0));
+        octants[octant] += 1;
+        mean = mean + p / f64::from(n);
+    }
+    // uniform: no preferred direction, every octant holds an eighth of the samples
+    assert!(mean.size() < 0.02, "{mean:?}");
+    for count in octants {
+        let share = f64::from(count) / f64::from(n);
+        assert!((share - 0.125).abs() < 0.01, "{octants:?}");
+    }
+}
@@ -1,34 +1,196 @@
-use super::point3::Point;
+use std::sync::Arc;
 
+use super::{point3::Point, rgb::ARgb};
+
+// deeper nesting drops the outermost medium
+const MAX_NESTED_MEDIA: usize = 6;
+
+// dielectric volume a ray is travelling inside of
 #[derive(Copy, Clone, Default, Debug)]
+pub struct MediumEntry {
+    // identity of the material which owns the volume
+    pub id: usize,
+    // overlapping volumes are resolved in favour of higher priority
+    pub priority: u32,
+    pub ior: f64,
+    pub absorption: Option<ARgb>,
+}
+
+impl MediumEntry {
+    // Beer-Lambert, distance in world units and may be infinite
+    #[must_use]
+    pub fn transmittance(&self, distance: f64) -> ARgb {
+        match self.absorption {
+            Some(absorption) => absorption.map(|a| {
+                if a > 0.0 {
+                    f64::exp(-a * distance)
+                } else {
+                    1.0
+                }
+            }),
+            None => ARgb::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+// volumes the ray is inside of, in order of entering
+#[derive(Copy, Clone, Default, Debug)]
+pub struct MediumStack {
+    entries: [MediumEntry; MAX_NESTED_MEDIA],
+    len: usize,
+}
+
+const NO_MEDIA: MediumStack = MediumStack {
+    entries: [MediumEntry {
+        id: 0,
+        priority: 0,
+        ior: 1.0,
+        absorption: None,
+    }; MAX_NESTED_MEDIA],
+    len: 0,
+};
+
+impl MediumStack {
+    // medium which actually fills the space around the ray:
+    // highest priority, the most recently entered on ties
+    #[must_use]
+    pub fn current(&self) -> Option<&MediumEntry> {
+        // max_by_key returns the last of equal maximums
+        self.entries[..self.len].iter().max_by_key(|e| e.priority)
+    }
+
+    #[must_use]
+    pub fn contains(&self, id: usize) -> bool {
+        self.entries[..self.len].iter().any(|e| e.id == id)
+    }
+
+    pub fn push(&mut self, entry: MediumEntry) {
+        if self.len == MAX_NESTED_MEDIA {
+            self.entries.rotate_left(1);
+            self.len -= 1;
+        }
+        self.entries[self.len] = entry;
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if let Some(i) = self.entries[..self.len].iter().rposition(|e| e.id == id) {
+            self.entries[i..self.len].rotate_left(1);
+            self.len -= 1;
+        }
+    }
+}
+
+#[derive(Clone, Default, Debug)]
 pub struct Ray {
     orig: Point,
     dir: Point,
     tm: f64,
+    // shared by the rays continuing in the same media, None outside of any,
+    // keeps rays small for the common case
+    media: Option<Arc<MediumStack>>,
 }
 
 impl Ray {
+    #[must_use]
     pub fn new(orig: Point, dir: Point, tm: Option<f64>) -> Self {
         Ray {
             orig,
             dir,
             tm: tm.unwrap_or_default(),
+            media: None,
+        }
+    }
+
+    // ray from orig in dir at the same time and inside of the same media
+    #[must_use]
+    pub fn continued(&self, orig: Point, dir: Point) -> Self {
+        Ray {
+            orig,
+            dir,
+            tm: self.tm,
+            media: self.media.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_media(self, media: &MediumStack) -> Self {
+        Ray {
+            media: (media.len > 0).then(|| Arc::new(*media)),
+            ..self
         }
     }
 
+    #[must_use]
     pub fn dir(&self) -> Point {
         self.dir
     }
 
+    #[must_use]
     pub fn orig(&self) -> Point {
         self.orig
     }
 
+    #[must_use]
     pub fn at(&self, t: f64) -> Point {
         self.orig + self.dir * t
     }
 
+    #[must_use]
     pub fn time(&self) -> f64 {
         self.tm
     }
+
+    #[must_use]
+    pub fn media(&self) -> &MediumStack {
+        self.media.as_deref().unwrap_or(&NO_MEDIA)
+    }
+
+    // absorption along the ray up to parameter t by the medium it travels through
+    #[must_use]
+    pub fn transmittance(&self, t: f64) -> ARgb {
+        self.media()
+            .current()
+            .map_or(ARgb::new(1.0, 1.0, 1.0), |m| {
+                m.transmittance(t * self.dir.size())
+            })
+    }
+}
+
+#[test]
+fn test_medium_stack_priority() {
+    let entry = |id, priority, ior| MediumEntry {
+        id,
+        priority,
+        ior,
+        absorption: None,
+    };
+    let mut media = MediumStack::default();
+    media.push(entry(1, 1, 1.33));
+    media.push(entry(2, 0, 1.5));
+    // glass entered later but water has priority in the overlap
+    assert_eq!(media.current().map(|m| m.id), Some(1));
+    media.remove(1);
+    assert_eq!(media.current().map(|m| m.id), Some(2));
+    media.push(entry(3, 0, 1.31));
+    // same priority, the most recently entered wins
+    assert_eq!(media.current().map(|m| m.id), Some(3));
+    assert!(!media.contains(1));
+}
+
+#[test]
+fn test_rays_share_media_out_of_line() {
+    let mut media = MediumStack::default();
+    media.push(MediumEntry {
+        id: 1,
+        priority: 0,
+        ior: 1.33,
+        absorption: None,
+    });
+    let inside = Ray::new(Point::default(), Point::new(0.0, 0.0, 1.0), None).with_media(&media);
+    let bounced = inside.continued(Point::new(0.0, 0.0, 1.0), Point::new(1.0, 0.0, 0.0));
+    assert_eq!(bounced.media().current().map(|m| m.id), Some(1));
+    // the stack is not copied with the ray
+    assert!(std::mem::size_of::<Ray>() <= 64);
+    assert!(Ray::default().media().current().is_none());
 }
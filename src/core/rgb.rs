@@ -12,7 +12,7 @@ pub const SOLID_CYAN_COLOR: ARgb = ARgb {
     rgb: [0.0, 1.0, 1.0],
 };
 
-#[derive(Clone, Copy, Default)]
+#[derive(Clone, Copy, Default, Debug)]
 pub struct ARgb {
     rgb: [f64; 3],
 }
@@ -27,10 +27,7 @@ fn linear_to_gamma(linear: f64) -> f64 {
 
 impl std::fmt::Display for ARgb {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let u8r = self
-            .rgb
-            .map(linear_to_gamma)
-            .map(|x| safe_f64_to_u8_clamp(x * 254.999).expect("f64 is nan!"));
+        let u8r = self.to_rgb8();
         writeln!(f, "{} {} {}", u8r[0], u8r[1], u8r[2])
     }
 }
@@ -45,10 +42,39 @@ impl From<Rgb<u8>> for ARgb {
 }
 
 impl ARgb {
+    #[must_use]
     pub fn new(r: f64, g: f64, b: f64) -> Self {
         ARgb { rgb: [r, g, b] }
     }
 
+    #[must_use]
+    pub fn channels(&self) -> [f64; 3] {
+        self.rgb
+    }
+
+    #[must_use]
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Self {
+        Self {
+            rgb: self.rgb.map(f),
+        }
+    }
+
+    // relative luminance of linear rgb, rec. 709 weights
+    #[must_use]
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.rgb[0] + 0.7152 * self.rgb[1] + 0.0722 * self.rgb[2]
+    }
+
+    // gamma encoded 8 bit channels, as written to images
+    // panics on nan channels
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_rgb8(self) -> [u8; 3] {
+        self.rgb
+            .map(linear_to_gamma)
+            .map(|x| safe_f64_to_u8_clamp(x * 254.999).expect("f64 is nan!"))
+    }
+
+    #[allow(clippy::missing_errors_doc)]
     pub fn write(&self, mut stream: impl Write) -> Result<()> {
         stream.write_all(self.to_string().as_bytes())?;
         Ok(())
@@ -0,0 +1,4 @@
+pub mod camera;
+pub mod core;
+pub mod scene;
+pub mod utils;
@@ -1,27 +1,20 @@
-mod camera;
-mod core;
-mod scene;
-mod utils;
-
-use core::{point3::Point, rgb::ARgb};
-use std::{
-    f64::consts::PI,
-    path::{Path, PathBuf},
-    sync::Arc,
-};
+use std::{f64::consts::PI, path::PathBuf, sync::Arc};
 
-use camera::camera::{InitError, RenderError};
 use rand::Rng;
-use scene::{
-    hittable::{Hittable, Scene},
-    image_loader::load_image_to_rgb,
-    material::{Dielectric, Lambertian, Material, Metal},
-    sphere::Sphere,
-    texture::{CheckerTexture, ImageTexture, Texture},
+use raytracer::{
+    camera::camera::{Camera, InitError, RenderError},
+    core::{point3::Point, rgb::ARgb},
+    scene::{
+        hittable::{Hittable, Scene},
+        image_loader::load_image_to_rgba,
+        material::{Dielectric, Lambertian, Material, Metal},
+        normal_map::NormalMapped,
+        sphere::Sphere,
+        subsurface::Subsurface,
+        texture::{CheckerTexture, Filter, ImageTexture, NoiseTexture, Texture, Wrap},
+    },
 };
 
-use crate::camera::camera::Camera;
-
 const MARS_TEXTURE: &str = "mars_1k_color.jpg";
 const EARTH_TEXTURE: &str = "earthmap.jpg";
 const TEXTURES_PATH: &str = "./presets/textures/";
@@ -29,8 +22,8 @@ const TEXTURES_PATH: &str = "./presets/textures/";
 fn main() {
     let img_width: u32 = 400;
     let ratio = 16.0 / 9.0;
-    let lookfrom = Point::new(13.0, 2.0, 3.0);
-    let _lookfrom = Point::default();
+    let _lookfrom = Point::new(13.0, 2.0, 3.0);
+    let _lookfrom_origin = Point::default();
     let lookat = Point::new(0.0, 0.0, 0.0);
     let _lookat = Point::new(0.0, 0.0, -1.0);
     let vup = Point::new(0.0, 1.0, 0.0);
@@ -88,7 +81,8 @@ fn main() {
     let mat_ground: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.8, 0.8, 0.1), 1.0));
     let mat_center: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.7, 0.2, 0.8), 1.0));
     let mat_left: Arc<dyn Material> = Arc::new(Dielectric::new(1.5));
-    let mat_left_bubble: Arc<dyn Material> = Arc::new(Dielectric::new(1.0 / 1.5));
+    // air bubble inside of the glass, takes over the overlap thanks to higher priority
+    let mat_left_bubble: Arc<dyn Material> = Arc::new(Dielectric::new(1.0).with_priority(1));
     let mat_right: Arc<dyn Material> = Arc::new(Metal::new(ARgb::new(0.8, 0.6, 0.2), Some(0.5)));
 
     let mut scene = Scene::default();
@@ -123,23 +117,45 @@ fn main() {
     let mut scene3 = bouncing_balls_scene();
     scene3.build_bvh();
 
-    let mars_scene = mars_texture_scene();
-    if let Err(e) = c.render(&mars_scene) {
+    // preset picked by the first argument, e.g. `cargo run --release -- subsurface > out.ppm`
+    let preset = std::env::args().nth(1);
+    let Some(preset_scene) = preset_scene(preset.as_deref()) else {
+        eprintln!("unknown scene, expected one of: {}", PRESETS.join(", "));
+        return;
+    };
+    if let Err(e) = c.render(&preset_scene) {
         eprint!("render error: ");
         match e {
             RenderError::WriteHeader(e) => eprintln!("error writing P3 header {e}"),
             RenderError::WritePx(e) => eprintln!("error writing P3 pixel {e}"),
+            RenderError::SavePng(e) => eprintln!("error saving png {e}"),
         }
     }
 }
 
+const PRESETS: [&str; 3] = ["mars", "subsurface", "bump"];
+
+fn preset_scene(name: Option<&str>) -> Option<Scene> {
+    match name {
+        None | Some("mars") => Some(mars_texture_scene()),
+        Some("subsurface") => Some(backlit_subsurface_scene()),
+        Some("bump") => Some(bumpy_ball_scene()),
+        Some(_) => None,
+    }
+}
+
 fn mars_texture_scene() -> Scene {
     let mut scene = Scene::default();
     let mut texture_path = PathBuf::from(TEXTURES_PATH);
     texture_path.push(EARTH_TEXTURE);
-    let rgb_image = load_image_to_rgb(texture_path).expect("failed to load image");
-    let mars_texture = Arc::new(ImageTexture::new(Arc::new(rgb_image))) as Arc<dyn Texture>;
-    let mars_material = Arc::new(Lambertian::with_texture(&mars_texture, 1.0));
+    let rgba_image = load_image_to_rgba(texture_path).expect("failed to load image");
+    // longitude wraps around the sphere, latitude ends at the poles
+    let mars_texture = Arc::new(
+        ImageTexture::new(Arc::new(rgba_image))
+            .with_filter(Filter::Bilinear)
+            .with_wrap(Wrap::Repeat, Wrap::Clamp),
+    ) as Arc<dyn Texture>;
+    let mars_material: Arc<dyn Material> = Arc::new(Lambertian::with_texture(&mars_texture, 1.0));
 
     let mars_surface =
         Arc::new(Sphere::new_static(1.8, Point::default(), mars_material)) as Arc<dyn Hittable>;
@@ -153,8 +169,8 @@ fn bouncing_balls_scene() -> Scene {
 
     let mut texture_path = PathBuf::from(TEXTURES_PATH);
     texture_path.push(MARS_TEXTURE);
-    let rgb_image = load_image_to_rgb(texture_path).expect("failed to load image");
-    let mars_texture = Arc::new(ImageTexture::new(Arc::new(rgb_image))) as Arc<dyn Texture>;
+    let rgba_image = load_image_to_rgba(texture_path).expect("failed to load image");
+    let mars_texture = Arc::new(ImageTexture::new(Arc::new(rgba_image))) as Arc<dyn Texture>;
     let mars_material = Arc::new(Lambertian::with_texture(&mars_texture, 1.0));
     let near3 = Point::new(-4.0, 1.0, 0.0);
     let near1 = Point::new(4.0, 1.0, 0.0);
@@ -180,7 +196,7 @@ fn bouncing_balls_scene() -> Scene {
 
     let yellow = ARgb::new(1.0, 1.0, 0.3);
     let yellow_lambert: Arc<dyn Material> = Arc::new(Lambertian::new(yellow, 1.0));
-    let yellow_lamber_sphere: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+    let _yellow_lamber_sphere: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
         1.0,
         Point::new(-4.0, 1.0, 0.0),
         Arc::clone(&yellow_lambert),
@@ -190,9 +206,9 @@ fn bouncing_balls_scene() -> Scene {
     let metallic_sphere: Arc<dyn Hittable> =
         Arc::new(Sphere::new_static(1.0, near3, Arc::clone(&metallic)));
 
-    [glass_sphere, mars_surface, metallic_sphere]
-        .into_iter()
-        .for_each(|x| scene.add(&x));
+    for x in [glass_sphere, mars_surface, metallic_sphere] {
+        scene.add(&x);
+    }
 
     (-11..11).for_each(|a| {
         (-11..11).for_each(|b| {
@@ -227,3 +243,52 @@ fn bouncing_balls_scene() -> Scene {
 
     scene
 }
+
+// wax ball with a light right behind it, thin rim and the side facing the light glow
+// through, which a lambertian ball of the same colour can't do
+fn backlit_subsurface_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    let ground_material: Arc<dyn Material> =
+        Arc::new(Lambertian::new(ARgb::new(0.4, 0.4, 0.4), 1.0));
+    let ground: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1000.0,
+        Point::new(0.0, -1001.0, 0.0),
+        ground_material,
+    ));
+    scene.add(&ground);
+
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1.0,
+        Point::default(),
+        Arc::new(Dielectric::new(1.4)),
+    ));
+    // wax: red travels furthest and is absorbed the least
+    let wax: Arc<dyn Hittable> = Arc::new(Subsurface::new(
+        boundary,
+        ARgb::new(0.3, 0.24, 0.18),
+        ARgb::new(0.995, 0.95, 0.8),
+        1.4,
+    ));
+    scene.add(&wax);
+    scene.add_sphere_light(0.4, Point::new(0.0, 0.3, -1.8), ARgb::new(40.0, 36.0, 30.0));
+
+    scene.build_bvh();
+    scene
+}
+
+// dented clay without extra geometry: perlin noise as the height field of a bump
+// map, lit from the side so the dents cast soft shading
+fn bumpy_ball_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    let height: Arc<dyn Texture> = Arc::new(NoiseTexture::new(7, 4.0));
+    let smooth: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.7, 0.55, 0.4), 1.0));
+    let dented: Arc<dyn Material> = Arc::new(NormalMapped::bump(&smooth, &height, 0.3));
+    let ball: Arc<dyn Hittable> = Arc::new(Sphere::new_static(1.8, Point::default(), dented));
+    scene.add(&ball);
+    scene.add_sphere_light(0.5, Point::new(-4.0, 3.0, 4.0), ARgb::new(30.0, 30.0, 30.0));
+
+    scene.build_bvh();
+    scene
+}
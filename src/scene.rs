@@ -1,7 +1,23 @@
 pub mod aabb;
 pub mod bvh;
+pub mod coated;
+pub mod constant_medium;
 pub mod hittable;
 pub mod image_loader;
+pub mod light;
+pub mod light_bvh;
 pub mod material;
+pub mod medium;
+pub mod mesh;
+pub mod microfacet;
+pub mod noise;
+pub mod normal_map;
+pub mod phase;
+pub mod principled;
+pub mod quad;
+pub mod sheen;
 pub mod sphere;
+pub mod subsurface;
 pub mod texture;
+pub mod thin_film;
+pub mod voxel_volume;
@@ -27,6 +27,7 @@ pub struct Aabb {
 }
 
 impl Aabb {
+    #[must_use]
     pub fn new(x: &Interval, y: &Interval, z: &Interval) -> Self {
         Aabb {
             x: *x,
@@ -35,6 +36,7 @@ impl Aabb {
         }
     }
 
+    #[must_use]
     pub fn from_points(a: &Point, b: &Point) -> Self {
         let [x, y, z] = [0, 1, 2].map(|i| {
             let (min, max) = if a.e[i] <= b.e[i] {
@@ -48,6 +50,7 @@ impl Aabb {
         Self { x, y, z }
     }
 
+    #[must_use]
     pub fn merge(lhs: &Aabb, rhs: &Aabb) -> Aabb {
         Aabb::new(
             &Interval::enclosing(&lhs.x, &rhs.x),
@@ -56,10 +59,25 @@ impl Aabb {
         )
     }
 
+    #[must_use]
     pub fn expand(&self, other: &Aabb) -> Aabb {
         Aabb::merge(self, other)
     }
 
+    // flat primitives have zero width along some axis, which rays can't hit
+    #[must_use]
+    pub fn padded(&self, delta: f64) -> Aabb {
+        let pad = |i: &Interval| {
+            if i.size() < delta {
+                i.expand(delta / 2.0)
+            } else {
+                *i
+            }
+        };
+        Aabb::new(&pad(&self.x), &pad(&self.y), &pad(&self.z))
+    }
+
+    #[must_use]
     pub fn axis_interval(&self, n: Axis) -> &Interval {
         match n {
             Axis::X => &self.x,
@@ -68,12 +86,14 @@ impl Aabb {
         }
     }
 
+    #[must_use]
     pub fn hit(&self, r: &Ray, ray_t: &Interval) -> bool {
         crate::utils::math::AXES
             .iter()
             .all(|&axis| self.find_ray_hit_boundaries(r, axis, ray_t))
     }
 
+    #[must_use]
     pub fn find_ray_hit_boundaries(&self, r: &Ray, axis: Axis, ray_t: &Interval) -> bool {
         let ax_interval = Aabb::axis_interval(self, axis);
         let axis: usize = axis.into();
@@ -94,6 +114,29 @@ impl Aabb {
         ray_t.max > ray_t.min
     }
 
+    // part of ray_t where the ray is inside of the box, None if it misses
+    #[must_use]
+    pub fn hit_interval(&self, r: &Ray, ray_t: &Interval) -> Option<Interval> {
+        let mut ray_t = ray_t.to_owned();
+        for axis in crate::utils::math::AXES {
+            let ax_interval = self.axis_interval(axis);
+            let axis: usize = axis.into();
+            let adinv = (r.dir().e[axis]).recip();
+            let t0 = (ax_interval.min - r.orig().e[axis]) * adinv;
+            let t1 = (ax_interval.max - r.orig().e[axis]) * adinv;
+            let (min_t, max_t) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            ray_t.min = f64::max(ray_t.min, min_t);
+            ray_t.max = f64::min(ray_t.max, max_t);
+            if ray_t.max <= ray_t.min {
+                return None;
+            }
+        }
+        Some(ray_t)
+    }
+
+    // panics on nan bounds
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
     pub fn compare_over_axis(&self, other: &Aabb, axis: Axis) -> std::cmp::Ordering {
         self.axis_interval(axis)
             .min
@@ -101,6 +144,9 @@ impl Aabb {
             .expect("interval contains NaN which is impossible to compare")
     }
 
+    // panics on nan bounds
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
     pub fn longest_axis(&self) -> Axis {
         let (xsize, ysize, zsize) = (self.x.size(), self.y.size(), self.z.size());
         (match xsize.partial_cmp(&ysize) {
@@ -17,7 +17,9 @@ enum Either {
 
 pub struct Bvh {
     left: Arc<dyn Hittable>,
-    right: Arc<dyn Hittable>,
+    // None in a leaf with a single object, stochastic objects (media, stochastic alpha)
+    // must not be sampled twice
+    right: Option<Arc<dyn Hittable>>,
     bbox: Aabb,
 }
 
@@ -28,21 +30,21 @@ impl Bvh {
     pub fn new(objects: &mut [Arc<dyn Hittable>]) -> Self {
         // build bbox of the span of source objects
         let mut bbox = aabb::EMPTY;
-        objects
-            .iter()
-            .for_each(|obj| bbox = bbox.expand(obj.bounding_box()));
+        for obj in objects.iter() {
+            bbox = bbox.expand(obj.bounding_box());
+        }
 
         let axis = bbox.longest_axis();
 
         let (left, right) = match objects.len() {
             0 => unreachable!(),
-            1 => (Arc::clone(&objects[0]), (Arc::clone(&objects[0]))),
+            1 => (Arc::clone(&objects[0]), None),
             2 => {
                 let left = Arc::clone(&objects[0]);
                 let right = Arc::clone(&objects[1]);
                 match Self::compare_hittables(&left, &right, axis) {
-                    Either::Left => (left, right),
-                    Either::Right => (right, left),
+                    Either::Left => (left, Some(right)),
+                    Either::Right => (right, Some(left)),
                 }
             }
             span => {
@@ -50,7 +52,7 @@ impl Bvh {
                 let mid = span / 2;
                 (
                     Arc::new(Self::new(&mut objects[..mid])) as Arc<dyn Hittable>,
-                    Arc::new(Self::new(&mut objects[mid..])) as Arc<dyn Hittable>,
+                    Some(Arc::new(Self::new(&mut objects[mid..])) as Arc<dyn Hittable>),
                 )
             }
         };
@@ -79,11 +81,12 @@ impl Hittable for Bvh {
     fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
         if self.bbox.hit(ray, ray_t_possible) {
             let hit_left = self.left.hit(ray, ray_t_possible);
+            let Some(ref right) = self.right else {
+                return hit_left;
+            };
             let hit_right = match hit_left {
-                Some(ref left_hr) => self
-                    .right
-                    .hit(ray, &Interval::new(ray_t_possible.min, left_hr.t)),
-                None => self.right.hit(ray, ray_t_possible),
+                Some(ref left_hr) => right.hit(ray, &Interval::new(ray_t_possible.min, left_hr.t)),
+                None => right.hit(ray, ray_t_possible),
             };
             hit_right.or(hit_left)
         } else {
@@ -94,4 +97,117 @@ impl Hittable for Bvh {
     fn bounding_box(&self) -> &Aabb {
         &self.bbox
     }
+
+    // no need to shrink the interval by the left hit, any hit is enough
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.bbox.hit(ray, ray_t_possible)
+            && (self.left.occluded(ray, ray_t_possible)
+                || self
+                    .right
+                    .as_ref()
+                    .is_some_and(|right| right.occluded(ray, ray_t_possible)))
+    }
+}
+
+#[cfg(test)]
+fn random_spheres_bvh(n: usize) -> Bvh {
+    use super::{material::Lambertian, sphere::Sphere};
+    use crate::core::{point3::Point, rgb::ARgb};
+    use rand::{Rng, SeedableRng};
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+    let mat: Arc<dyn super::material::Material> =
+        Arc::new(Lambertian::new(ARgb::new(0.5, 0.5, 0.5), 1.0));
+    let objects: Vec<Arc<dyn Hittable>> = (0..n)
+        .map(|_| {
+            let center = Point::new(
+                rng.random_range(-10.0..10.0),
+                rng.random_range(-10.0..10.0),
+                rng.random_range(-10.0..10.0),
+            );
+            Arc::new(Sphere::new_static(0.3, center, Arc::clone(&mat))) as Arc<dyn Hittable>
+        })
+        .collect();
+    Bvh::from_vec(&objects)
+}
+
+#[cfg(test)]
+fn random_rays(n: usize) -> Vec<Ray> {
+    use crate::core::point3::Point;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(11);
+    (0..n)
+        .map(|_| {
+            Ray::new(
+                Point::random_unit_on_sphere(&mut rng) * 15.0,
+                Point::random_unit_on_sphere(&mut rng),
+                None,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_occluded_agrees_with_hit() {
+    let bvh = random_spheres_bvh(500);
+    let t = Interval::new(0.001, f64::INFINITY);
+    for ray in random_rays(2000) {
+        assert_eq!(bvh.occluded(&ray, &t), bvh.hit(&ray, &t).is_some());
+    }
+}
+
+#[test]
+fn test_single_object_leaf_samples_medium_once() {
+    use super::{constant_medium::ConstantMedium, material::Lambertian, sphere::Sphere};
+    use crate::core::{point3::Point, rgb::ARgb};
+
+    // fog ball one unit across with unit density, the central ray scatters with 1 - 1/e
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        0.5,
+        Point::default(),
+        Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0)),
+    ));
+    let fog: Arc<dyn Hittable> =
+        Arc::new(ConstantMedium::new(boundary, 1.0, ARgb::new(1.0, 1.0, 1.0)));
+    let bvh = Bvh::from_vec(&[fog]);
+    let ray = Ray::new(Point::new(0.0, 0.0, -2.0), Point::new(0.0, 0.0, 1.0), None);
+    let t = Interval::new(0.001, f64::INFINITY);
+
+    let n = 20_000;
+    let hits = (0..n).filter(|_| bvh.hit(&ray, &t).is_some()).count();
+    let blocked = (0..n).filter(|_| bvh.occluded(&ray, &t)).count();
+    let expected = 1.0 - f64::exp(-1.0);
+    for count in [hits, blocked] {
+        #[allow(clippy::cast_precision_loss)]
+        let p = count as f64 / f64::from(n);
+        assert!((p - expected).abs() < 0.02, "{p} vs {expected}");
+    }
+}
+
+// cargo test --release bench_occluded_vs_hit -- --ignored --nocapture
+#[test]
+#[ignore = "benchmark, run manually"]
+fn bench_occluded_vs_hit() {
+    use std::time::Instant;
+
+    let bvh = random_spheres_bvh(10_000);
+    let rays = random_rays(200_000);
+    let t = Interval::new(0.001, f64::INFINITY);
+
+    let start = Instant::now();
+    let hits = rays.iter().filter(|r| bvh.hit(r, &t).is_some()).count();
+    let closest = start.elapsed();
+
+    let start = Instant::now();
+    let blocked = rays.iter().filter(|r| bvh.occluded(r, &t)).count();
+    let any = start.elapsed();
+
+    assert_eq!(hits, blocked);
+    println!(
+        "closest-hit: {closest:?}, any-hit: {any:?}, {hits} of {} rays blocked",
+        rays.len()
+    );
 }
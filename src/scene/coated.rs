@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::core::{onb::Onb, point3::Point, ray::Ray, rgb::ARgb};
+
+use super::{
+    hittable::HitRec,
+    material::Material,
+    microfacet::{self, TrowbridgeReitz},
+};
+
+// Dielectric coat (varnish, car paint clearcoat) over any base material.
+// The coat reflects by fresnel at the macro surface, the rest goes through the coat,
+// is scattered by the base and attenuated on the way out. Refraction inside the coat only
+// changes the path length through the absorbing layer, the base sees the original
+// directions, and light bouncing between the base and the underside of the coat is ignored.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    ior: f64,
+    distribution: TrowbridgeReitz,
+    // absorption coefficient of the coat and its thickness, in the same units
+    absorption: Option<(ARgb, f64)>,
+}
+
+impl Coated {
+    pub fn new(base: &Arc<dyn Material>, ior: f64, roughness: f64) -> Self {
+        Self {
+            base: Arc::clone(base),
+            ior,
+            distribution: TrowbridgeReitz::from_roughness(roughness, 0.0),
+            absorption: None,
+        }
+    }
+
+    // tinted coats, e.g. amber varnish over wood
+    #[must_use]
+    pub fn with_absorption(mut self, absorption: ARgb, thickness: f64) -> Self {
+        self.absorption = Some((absorption, thickness));
+        self
+    }
+
+    fn fresnel(&self, cos: f64) -> f64 {
+        microfacet::fresnel_dielectric(cos.abs(), self.ior)
+    }
+
+    // transmittance of the coat for light entering along cos_o and leaving along cos_i
+    fn transmittance(&self, cos_o: f64, cos_i: f64) -> ARgb {
+        let Some((absorption, thickness)) = self.absorption else {
+            return ARgb::new(1.0, 1.0, 1.0);
+        };
+        // cosine of the refracted direction inside of the coat
+        let inside = |cos: f64| f64::sqrt(1.0 - (1.0 - cos * cos) / (self.ior * self.ior));
+        let distance = thickness * (1.0 / inside(cos_o) + 1.0 / inside(cos_i));
+        absorption.map(|a| f64::exp(-a * distance))
+    }
+
+    // both fresnel transmissions and the absorption of the coat
+    fn through_coat(&self, cos_o: f64, cos_i: f64) -> ARgb {
+        self.transmittance(cos_o, cos_i)
+            * ((1.0 - self.fresnel(cos_o)) * (1.0 - self.fresnel(cos_i)))
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let mut rng = rand::rng();
+        let f_macro = self.fresnel(wo.z());
+        if rng.random::<f64>() < f_macro {
+            // coat reflection, with fresnel of the macro surface as its probability
+            if self.distribution.effectively_smooth() {
+                let wi = Point::new(-wo.x(), -wo.y(), wo.z());
+                *scattered = r_in.continued(hr.p, onb.local_to_world(&wi));
+                *attenuation = ARgb::new(1.0, 1.0, 1.0);
+                return true;
+            }
+            let wm = self.distribution.sample_wm(&wo, &mut rng);
+            let wi = microfacet::reflect(&wo, &wm);
+            if wi.z() <= 0.0 {
+                return false;
+            }
+            let weight = self.fresnel(wo.scalar_prod(&wm)) / f_macro
+                * self.distribution.g(&wo, &wi)
+                / self.distribution.g1(&wo);
+            *scattered = r_in.continued(hr.p, onb.local_to_world(&wi));
+            *attenuation = ARgb::new(weight, weight, weight);
+            return true;
+        }
+
+        // through the coat, entering fresnel cancels with the probability of getting here
+        if !self.base.scatter(r_in, attenuation, scattered, hr) {
+            return false;
+        }
+        let cos_i = scattered.dir().unit().scalar_prod(&hr.n);
+        *attenuation =
+            *attenuation * self.transmittance(wo.z(), cos_i) * (1.0 - self.fresnel(cos_i));
+        true
+    }
+
+    // Light sampling is only possible when both layers can be evaluated: with a smooth
+    // coat (or a specular base) reflected emission has to be found by scattered rays.
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let base = self.base.eval(r_in, wi, hr)?;
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some(ARgb::default());
+        }
+
+        let wm = (wo + wi).unit();
+        let d = &self.distribution;
+        let coat = self.fresnel(wo.scalar_prod(&wm)) * d.d(&wm) * d.g(&wo, &wi) / (4.0 * wo.z());
+        Some(ARgb::new(coat, coat, coat) + base * self.through_coat(wo.z(), wi.z()))
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.base.emitted(u, v, p)
+    }
+}
+
+#[test]
+fn test_coat_reflects_fresnel_at_normal_incidence() {
+    use super::material::Lambertian;
+
+    // black base: everything coming back is the coat reflection, ((1.5 - 1) / 2.5)^2
+    let black: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::default(), 1.0));
+    let coated = Coated::new(&black, 1.5, 0.0);
+    let n = Point::new(0.0, 0.0, 1.0);
+    let hr = HitRec::new(Point::default(), n, 1.0, Arc::clone(&black));
+    let r_in = Ray::new(n, -n, None);
+
+    let count = 40_000;
+    let mut reflected = 0.0;
+    for _ in 0..count {
+        let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+        assert!(coated.scatter(&r_in, &mut attenuation, &mut scattered, &hr));
+        if attenuation.luminance() > 0.0 {
+            // smooth coat mirrors straight back
+            assert_approx_eq::assert_approx_eq!(scattered.dir().unit().scalar_prod(&n), 1.0);
+            reflected += attenuation.luminance();
+        }
+    }
+    let reflected = reflected / f64::from(count);
+    assert!((reflected - 0.04).abs() < 0.005, "{reflected}");
+}
+
+#[test]
+fn test_coated_white_furnace_stays_below_one() {
+    use super::material::Lambertian;
+
+    // white base reflects everything, the coat may only lose energy on top of it
+    let white: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let n = Point::new(0.0, 0.0, 1.0);
+    let hr = HitRec::new(Point::default(), n, 1.0, Arc::clone(&white));
+    for roughness in [0.0, 0.2, 0.6] {
+        for coat in [
+            Coated::new(&white, 1.5, roughness),
+            Coated::new(&white, 1.5, roughness).with_absorption(ARgb::new(0.5, 1.0, 2.0), 0.1),
+        ] {
+            for degrees in [0.0_f64, 50.0, 80.0] {
+                let theta = degrees.to_radians();
+                let dir = Point::new(theta.sin(), 0.0, -theta.cos());
+                let r_in = Ray::new(-dir, dir, None);
+                let count = 4_000;
+                let mut total = 0.0;
+                for _ in 0..count {
+                    let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+                    if coat.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+                        assert!(scattered.dir().scalar_prod(&n) > 0.0);
+                        total += attenuation.channels().into_iter().fold(0.0, f64::max);
+                    }
+                }
+                let albedo = total / f64::from(count);
+                // light trapped under the coat or masked by rough facets is dropped, so below one
+                assert!(
+                    albedo <= 1.0 + 0.01 && albedo > 0.5,
+                    "{roughness} {degrees} {albedo}"
+                );
+            }
+        }
+    }
+}
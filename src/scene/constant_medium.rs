@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::{
+    core::{point3::Point, ray::Ray, rgb::ARgb},
+    utils::interval::{self, Interval},
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRec, Hittable},
+    medium::{Interaction, Medium},
+    phase::IsotropicPhase,
+    texture::Texture,
+};
+
+// Volume of constant density enclosed by a boundary (fog, smoke, mist).
+// Ray passing through the volume scatters at exponentially distributed distance,
+// boundary is expected to be closed and convex, the ray enters and exits it once.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    medium: Medium,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: ARgb) -> Self {
+        Self::with_medium(boundary, Medium::isotropic(density, albedo))
+    }
+
+    pub fn with_texture(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        texture: &Arc<dyn Texture>,
+    ) -> Self {
+        Self::with_medium(
+            boundary,
+            Medium::grey(density, texture, Arc::new(IsotropicPhase)),
+        )
+    }
+
+    // e.g. Medium::new with per channel absorption/scattering and henyey-greenstein phase
+    pub fn with_medium(boundary: Arc<dyn Hittable>, medium: Medium) -> Self {
+        Self { boundary, medium }
+    }
+
+    // ray parameters where the ray enters and leaves the volume inside of ray_t
+    fn inside(&self, ray: &Ray, ray_t: &Interval) -> Option<(f64, f64)> {
+        // both boundary crossings along the whole line, origin may be inside of the volume
+        let enter = self.boundary.hit(ray, &interval::UNIVERSE)?;
+        let exit = self
+            .boundary
+            .hit(ray, &Interval::new(enter.t + 0.0001, f64::INFINITY))?;
+
+        let t_enter = f64::max(f64::max(enter.t, ray_t.min), 0.0);
+        let t_exit = f64::min(exit.t, ray_t.max);
+        (t_enter < t_exit).then_some((t_enter, t_exit))
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        let (t_enter, t_exit) = self.inside(ray, ray_t_possible)?;
+        let ray_length = ray.dir().size();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+
+        // coloured media are tracked with null collisions, rays getting through carry
+        // no weight, so whatever inside of the volume is closer than the boundary is
+        // reached with the right colour
+        match self.medium.track(distance_inside, &mut rand::rng()) {
+            Interaction::Scatter { distance, material } => {
+                let t = t_enter + distance / ray_length;
+                // normal and face are arbitrary, phase functions don't use them
+                let normal = Point::new(1.0, 0.0, 0.0);
+                Some(HitRec::new(ray.at(t), normal, t, material))
+            }
+            Interaction::Pass { .. } => None,
+        }
+    }
+
+    // coloured transmittance can't be expressed by a boolean, shadows are grey
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        let Some((t_enter, t_exit)) = self.inside(ray, ray_t_possible) else {
+            return false;
+        };
+        let distance_inside = (t_exit - t_enter) * ray.dir().size();
+        rand::rng().random::<f64>() >= self.medium.mean_transmittance(distance_inside)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+#[test]
+fn test_coloured_medium_tints_objects_inside_of_it() {
+    use super::{
+        hittable::Scene,
+        material::{Lambertian, Matte},
+        phase::IsotropicPhase,
+        quad::Quad,
+        sphere::Sphere,
+    };
+
+    // absorbing ball of radius 2, the central ray crosses 2 units of it to reach a card
+    // in the middle
+    let sigma_a = ARgb::new(0.2, 0.6, 1.2);
+    let grey = Arc::new(Lambertian::new(ARgb::new(0.5, 0.5, 0.5), 1.0));
+    let boundary: Arc<dyn Hittable> =
+        Arc::new(Sphere::new_static(2.0, Point::default(), grey.clone()));
+    let ink: Arc<dyn Hittable> = Arc::new(ConstantMedium::with_medium(
+        boundary,
+        Medium::new(sigma_a, ARgb::default(), Arc::new(IsotropicPhase)),
+    ));
+    let card: Arc<dyn Hittable> = Arc::new(Quad::new(
+        Point::new(-1.0, -1.0, 0.0),
+        Point::new(2.0, 0.0, 0.0),
+        Point::new(0.0, 2.0, 0.0),
+        grey,
+    ));
+
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let expected = sigma_a.map(|s| f64::exp(-2.0 * s));
+    // whichever of the two the scene asks first
+    for objects in [[&ink, &card], [&card, &ink]] {
+        let mut scene = Scene::default();
+        for object in objects {
+            scene.add(object);
+        }
+        scene.build_bvh();
+
+        let n = 40_000;
+        let mut reached = ARgb::default();
+        for _ in 0..n {
+            let mut ray = Ray::new(Point::new(0.0, 0.0, -5.0), Point::new(0.0, 0.0, 1.0), None);
+            let mut throughput = ARgb::new(1.0, 1.0, 1.0);
+            while let Some(hr) = scene.hit(&ray, &ray_t) {
+                if hr.mat.is_volumetric() {
+                    // absorbed
+                    break;
+                }
+                if !matches!(hr.mat.matte(), Some(Matte::Transparent)) {
+                    reached = reached + throughput;
+                    break;
+                }
+                let (mut weight, mut next) = (ARgb::default(), Ray::default());
+                assert!(hr.mat.scatter(&ray, &mut weight, &mut next, &hr));
+                throughput = throughput * weight;
+                ray = next;
+            }
+        }
+        let reached = reached / f64::from(n);
+        for (got, want) in reached.channels().iter().zip(expected.channels()) {
+            assert!((got - want).abs() < 0.02, "{reached:?} vs {expected:?}");
+        }
+    }
+}
+
+#[test]
+fn test_fog_passes_by_exponential_transmittance() {
+    use super::{material::Lambertian, sphere::Sphere};
+
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1.0,
+        Point::default(),
+        Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0)),
+    ));
+    let fog = ConstantMedium::new(boundary, 0.8, ARgb::new(1.0, 1.0, 1.0));
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+
+    // chord off the centre, one starting inside, and one cut short by ray_t;
+    // with distance to the fog and through it
+    let cases = [
+        (Point::new(0.0, 0.6, -3.0), ray_t, 2.2, 1.6),
+        (Point::new(0.0, 0.0, 0.5), ray_t, 0.0, 0.5),
+        (
+            Point::new(0.0, 0.0, -3.0),
+            Interval::new(0.001, 1.25),
+            2.0,
+            0.5,
+        ),
+    ];
+    let n: u32 = 20_000;
+    for (orig, ray_t, entry, inside) in cases {
+        let ray = Ray::new(orig, Point::new(0.0, 0.0, 2.0), None);
+        let expected = f64::exp(-0.8 * inside);
+        let mut scatter_distance = 0.0;
+        let mut passed = 0_usize;
+        for _ in 0..n {
+            match fog.hit(&ray, &ray_t) {
+                Some(hr) => scatter_distance += (hr.p - orig).size(),
+                None => passed += 1,
+            }
+        }
+        let unblocked = (0..n).filter(|_| !fog.occluded(&ray, &ray_t)).count();
+        for count in [passed, unblocked] {
+            #[allow(clippy::cast_precision_loss)]
+            let p = count as f64 / f64::from(n);
+            assert!((p - expected).abs() < 0.015, "{p} vs {expected}");
+        }
+
+        // free flights which end inside follow the truncated exponential
+        #[allow(clippy::cast_precision_loss)]
+        let mean = scatter_distance / (f64::from(n) - passed as f64) - entry;
+        let sigma = 0.8;
+        let truncated = 1.0 / sigma - inside * expected / (1.0 - expected);
+        assert!((mean - truncated).abs() < 0.02, "{mean} vs {truncated}");
+    }
+}
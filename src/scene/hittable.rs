@@ -1,13 +1,23 @@
 use std::sync::Arc;
 
+use rand::Rng;
+
 use crate::{
-    core::{point3::Point, ray::Ray},
+    core::{onb::Onb, point3::Point, ray::Ray, rgb::ARgb},
     utils::interval::Interval,
 };
 
 use assert_approx_eq::assert_approx_eq;
 
-use super::{aabb::Aabb, bvh::Bvh, material::Material};
+use super::{
+    aabb::Aabb,
+    bvh::Bvh,
+    light::{Light, SphereLight},
+    light_bvh::LightBvh,
+    material::{DiffuseLight, Material},
+    sphere::Sphere,
+    texture::Texture,
+};
 
 #[derive(Clone, Copy, Debug, Default)]
 pub struct TextureCoord {
@@ -15,6 +25,7 @@ pub struct TextureCoord {
     pub v: f64,
 }
 
+#[derive(Clone)]
 pub struct HitRec {
     // point, normal vec and t as scalar of where hit happened
     pub p: Point,
@@ -23,9 +34,14 @@ pub struct HitRec {
     pub face: NormalFace,
     pub mat: Arc<dyn Material>,
     pub tx_coord: TextureCoord,
+    // derivatives of the point along texture coordinates, zero if the primitive
+    // has no parametrization
+    pub dpdu: Point,
+    pub dpdv: Point,
 }
 
 // where from happened ray hit, inside surface or outside
+#[derive(Clone, Copy)]
 pub enum NormalFace {
     Inside,
     Outside,
@@ -40,6 +56,8 @@ impl HitRec {
             tx_coord: TextureCoord { u: 0.0, v: 0.0 },
             face: NormalFace::Inside,
             mat,
+            dpdu: Point::default(),
+            dpdv: Point::default(),
         }
     }
     // We assume that every normal is in opposite direction to ray
@@ -62,11 +80,61 @@ impl HitRec {
     pub fn set_uv(&mut self, uv: (f64, f64)) {
         self.tx_coord = TextureCoord { u: uv.0, v: uv.1 }
     }
+
+    pub fn set_tangents(&mut self, dpdu: Point, dpdv: Point) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+    }
+
+    // local shading space around the normal, tangent along dp/du where it is known
+    #[must_use]
+    pub fn shading_frame(&self) -> Onb {
+        Onb::from_w_tangent(&self.n, &self.dpdu)
+    }
+}
+
+// how alpha of an opacity texture decides whether a surface is present at a hit
+#[derive(Clone, Copy, Debug)]
+pub enum AlphaMode {
+    // hits with alpha below the threshold are skipped (leaves, fences)
+    Cutout(f64),
+    // hit is kept with probability alpha, semi-transparent decals average out over samples
+    Stochastic,
+}
+
+// Opacity mask for primitives, rays pass through where the surface is cut out,
+// so the material never sees those hits
+pub struct Opacity {
+    texture: Arc<dyn Texture>,
+    mode: AlphaMode,
+}
+
+impl Opacity {
+    pub fn new(texture: &Arc<dyn Texture>, mode: AlphaMode) -> Self {
+        Self {
+            texture: Arc::clone(texture),
+            mode,
+        }
+    }
+
+    #[must_use]
+    pub fn keeps(&self, u: f64, v: f64, p: &Point) -> bool {
+        let alpha = self.texture.alpha(u, v, p);
+        match self.mode {
+            AlphaMode::Cutout(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => rand::rng().random::<f64>() < alpha,
+        }
+    }
 }
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec>;
     fn bounding_box(&self) -> &Aabb;
+    // any-hit query: true as soon as some intersection inside ray_t_possible is found.
+    // Primitives should override it to skip building HitRec (and cloning material Arc)
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.hit(ray, ray_t_possible).is_some()
+    }
 }
 
 #[derive(Default)]
@@ -74,6 +142,8 @@ pub struct Scene {
     objects: Vec<Arc<dyn Hittable>>,
     sum_aabb: Aabb,
     bvh: Option<Bvh>,
+    lights: Vec<Arc<dyn Light>>,
+    light_bvh: Option<LightBvh>,
 }
 
 impl Scene {
@@ -82,13 +152,48 @@ impl Scene {
         self.sum_aabb = self.sum_aabb.expand(object.bounding_box());
     }
 
-    // should always be called before hit check
+    pub fn add_light(&mut self, light: &Arc<dyn Light>) {
+        self.lights.push(Arc::clone(light));
+    }
+
+    // emissive sphere, both as geometry for rays to hit and as a light to sample
+    pub fn add_sphere_light(&mut self, r: f64, center: Point, radiance: ARgb) {
+        let mat: Arc<dyn Material> = Arc::new(DiffuseLight::sampled(radiance));
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new_static(r, center, mat));
+        let light: Arc<dyn Light> = Arc::new(SphereLight::new(center, r, radiance));
+        self.add(&sphere);
+        self.add_light(&light);
+    }
+
+    // should always be called before hit check, builds light sampling structure as well
     pub fn build_bvh(&mut self) {
         if self.bvh.is_none() {
             self.bvh = Some(Bvh::from_vec(&self.objects));
         }
+        if self.light_bvh.is_none() {
+            self.light_bvh = Some(LightBvh::new(&self.lights));
+        }
     }
 
+    // one light picked proportionally to its estimated contribution at p,
+    // along with probability of the pick
+    // panics before build_bvh
+    #[allow(clippy::missing_panics_doc)]
+    pub fn sample_light(
+        &self,
+        p: &Point,
+        n: Option<&Point>,
+        rng: &mut impl Rng,
+    ) -> Option<(&Arc<dyn Light>, f64)> {
+        self.light_bvh
+            .as_ref()
+            .expect("expected light bvh to exist when sample_light called")
+            .sample(p, n, rng)
+    }
+
+    // panics before build_bvh
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
     pub fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
         let bvh = self
             .bvh
@@ -96,4 +201,17 @@ impl Scene {
             .expect("expected bvh to exist when hit called");
         bvh.hit(ray, ray_t_possible)
     }
+
+    // visibility query for shadow and occlusion rays, we only care whether anything
+    // blocks the ray inside ray_t_possible, not what is the closest hit
+    // panics before build_bvh
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        let bvh = self
+            .bvh
+            .as_ref()
+            .expect("expected bvh to exist when occluded called");
+        bvh.occluded(ray, ray_t_possible)
+    }
 }
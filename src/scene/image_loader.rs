@@ -1,8 +1,10 @@
 use std::path::Path;
 
-use image::{ImageError, ImageReader, RgbImage};
+use image::{ImageError, ImageReader, RgbaImage};
 
-pub fn load_image_to_rgb<T: AsRef<Path>>(filename: T) -> Result<RgbImage, ImageError> {
+// alpha channel is kept for opacity masks, images without one are fully opaque
+#[allow(clippy::missing_errors_doc)]
+pub fn load_image_to_rgba<T: AsRef<Path>>(filename: T) -> Result<RgbaImage, ImageError> {
     let img = ImageReader::open(filename)?.decode()?;
-    Ok(img.to_rgb8())
+    Ok(img.to_rgba8())
 }
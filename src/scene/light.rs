@@ -0,0 +1,262 @@
+use std::f64::consts::{PI, TAU};
+
+use rand::Rng;
+
+use crate::core::{onb::Onb, point3::Point, rgb::ARgb};
+
+use super::{
+    aabb::Aabb,
+    light_bvh::{self, DirectionCone, LightBounds},
+};
+
+// incoming light at a shading point from one light.
+// wi is unit and points from the shading point towards the light,
+// li is radiance arriving along wi (already divided by sampling pdf)
+pub struct LightSample {
+    pub wi: Point,
+    pub dist: f64,
+    pub li: ARgb,
+}
+
+// Lights which are sampled explicitly with shadow rays.
+// Delta lights (point, spot, directional) have no surface so rays can never hit them,
+// thus the integrator is the only place they contribute.
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p: &Point, rng: &mut dyn rand::RngCore) -> Option<LightSample>;
+    // spatial and directional extent of emission for the light bvh,
+    // None for lights at infinity
+    fn bounds(&self) -> Option<LightBounds>;
+}
+
+// isotropic point emitter, intensity falls off with inverse square of distance
+pub struct PointLight {
+    pos: Point,
+    intensity: ARgb,
+}
+
+impl PointLight {
+    #[must_use]
+    pub fn new(pos: Point, intensity: ARgb) -> Self {
+        Self { pos, intensity }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: &Point, _rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        sample_positional(&self.pos, p, self.intensity)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(light_bvh::omnidirectional(
+            Aabb::from_points(&self.pos, &self.pos),
+            2.0 * TAU * self.intensity.luminance(),
+        ))
+    }
+}
+
+// point emitter restricted to a cone around dir.
+// Inside the cone intensity is scaled by cos(angle to axis)^falloff
+pub struct SpotLight {
+    pos: Point,
+    dir: Point,
+    cos_cone: f64,
+    falloff: f64,
+    intensity: ARgb,
+}
+
+impl SpotLight {
+    // cone_angle is half angle of the cone in radians
+    #[must_use]
+    pub fn new(pos: Point, dir: Point, cone_angle: f64, falloff: f64, intensity: ARgb) -> Self {
+        Self {
+            pos,
+            dir: dir.unit(),
+            cos_cone: cone_angle.cos(),
+            falloff: f64::max(falloff, 0.0),
+            intensity,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: &Point, _rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let cos_axis = (*p - self.pos).unit().scalar_prod(&self.dir);
+        if cos_axis < self.cos_cone {
+            return None;
+        }
+        sample_positional(&self.pos, p, self.intensity * cos_axis.powf(self.falloff))
+    }
+
+    // power is bounded by the one of a point light, the cone just doesn't spread further
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds {
+            bbox: Aabb::from_points(&self.pos, &self.pos),
+            phi: 2.0 * TAU * self.intensity.luminance(),
+            cone: DirectionCone::new(self.dir, self.cos_cone),
+            cos_theta_e: 0.0,
+        })
+    }
+}
+
+// sun-like light from infinitely far away, all rays are parallel to dir
+pub struct DirectionalLight {
+    dir: Point,
+    irradiance: ARgb,
+}
+
+impl DirectionalLight {
+    // dir is direction light travels in, e.g. (0, -1, 0) for the sun in zenith
+    #[must_use]
+    pub fn new(dir: Point, irradiance: ARgb) -> Self {
+        Self {
+            dir: dir.unit(),
+            irradiance,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: &Point, _rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.dir,
+            dist: f64::INFINITY,
+            li: self.irradiance,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+// Emissive sphere with constant radiance, sampled over the cone it subtends from the
+// shading point. Geometry for it is added to the scene separately (see Scene::add_sphere_light)
+pub struct SphereLight {
+    center: Point,
+    r: f64,
+    radiance: ARgb,
+}
+
+impl SphereLight {
+    #[must_use]
+    pub fn new(center: Point, r: f64, radiance: ARgb) -> Self {
+        Self {
+            center,
+            r: f64::max(r, 0.0),
+            radiance,
+        }
+    }
+}
+
+impl Light for SphereLight {
+    fn sample_li(&self, p: &Point, rng: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_center = self.center - *p;
+        let d2 = to_center.scalar_prod(&to_center);
+        let r2 = self.r * self.r;
+        // no sampling from inside of an emitter
+        if d2 <= r2 {
+            return None;
+        }
+        let d = d2.sqrt();
+        let cos_max = f64::sqrt(1.0 - r2 / d2);
+
+        let cos_theta = 1.0 - rng.random::<f64>() * (1.0 - cos_max);
+        let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = TAU * rng.random::<f64>();
+        let onb = Onb::from_w(&(to_center / d));
+        let wi = onb.local_to_world(&Point::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ));
+
+        // nearest intersection along wi, grazing rays are clamped to the tangent point
+        let dist = d * cos_theta - f64::sqrt(f64::max(0.0, r2 - d2 * sin_theta * sin_theta));
+        // uniform over the cone: pdf = 1 / solid angle
+        let solid_angle = TAU * (1.0 - cos_max);
+        Some(LightSample {
+            wi,
+            dist,
+            li: self.radiance * solid_angle,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let rvec = Point::new(self.r, self.r, self.r);
+        let area = 2.0 * TAU * self.r * self.r;
+        Some(light_bvh::omnidirectional(
+            Aabb::from_points(&(self.center - rvec), &(self.center + rvec)),
+            PI * area * self.radiance.luminance(),
+        ))
+    }
+}
+
+fn sample_positional(pos: &Point, p: &Point, intensity: ARgb) -> Option<LightSample> {
+    let to_light = *pos - *p;
+    let dist = to_light.size();
+    if dist == 0.0 {
+        return None;
+    }
+    Some(LightSample {
+        wi: to_light / dist,
+        dist,
+        li: intensity / (dist * dist),
+    })
+}
+
+#[cfg(test)]
+fn red_at(light: &dyn Light, p: Point) -> Option<f64> {
+    light
+        .sample_li(&p, &mut rand::rng())
+        .map(|s| s.li.channels()[0])
+}
+
+#[test]
+fn test_point_light_falls_off_with_inverse_square() {
+    let light = PointLight::new(Point::new(0.0, 5.0, 0.0), ARgb::new(8.0, 8.0, 8.0));
+    let near = light
+        .sample_li(&Point::new(0.0, 3.0, 0.0), &mut rand::rng())
+        .expect("point light is visible from everywhere");
+    assert_approx_eq::assert_approx_eq!(near.dist, 2.0);
+    assert_approx_eq::assert_approx_eq!(near.wi.y(), 1.0);
+    assert_approx_eq::assert_approx_eq!(near.li.channels()[0], 2.0);
+
+    let far = red_at(&light, Point::new(0.0, 1.0, 0.0)).expect("visible");
+    assert_approx_eq::assert_approx_eq!(far / near.li.channels()[0], 0.25);
+}
+
+#[test]
+fn test_spot_light_falls_off_towards_its_cone_and_cuts_off_outside() {
+    let cone = 30f64.to_radians();
+    let spot = |falloff| {
+        SpotLight::new(
+            Point::new(0.0, 1.0, 0.0),
+            Point::new(0.0, -1.0, 0.0),
+            cone,
+            falloff,
+            ARgb::new(1.0, 1.0, 1.0),
+        )
+    };
+    // points on the floor one unit below the light, at 20 degrees off the axis
+    let on_axis = Point::new(0.0, 0.0, 0.0);
+    let off_axis = Point::new(20f64.to_radians().tan(), 0.0, 0.0);
+    let dist2 = 1.0 + off_axis.x() * off_axis.x();
+
+    // without falloff the cone is a hard-edged point light
+    assert_approx_eq::assert_approx_eq!(red_at(&spot(0.0), on_axis).unwrap(), 1.0);
+    assert_approx_eq::assert_approx_eq!(red_at(&spot(0.0), off_axis).unwrap(), 1.0 / dist2);
+
+    let cos20 = 20f64.to_radians().cos();
+    assert_approx_eq::assert_approx_eq!(red_at(&spot(4.0), on_axis).unwrap(), 1.0);
+    assert_approx_eq::assert_approx_eq!(
+        red_at(&spot(4.0), off_axis).unwrap(),
+        cos20.powi(4) / dist2
+    );
+
+    // just inside and just outside of the 30 degree cone
+    let at_angle = |deg: f64| Point::new(deg.to_radians().tan(), 0.0, 0.0);
+    assert!(red_at(&spot(4.0), at_angle(29.9)).is_some_and(|li| li > 0.0));
+    assert!(red_at(&spot(4.0), at_angle(30.1)).is_none());
+    // and nothing behind the light
+    assert!(red_at(&spot(0.0), Point::new(0.0, 2.0, 0.0)).is_none());
+}
@@ -0,0 +1,432 @@
+use std::{
+    cmp::Ordering,
+    f64::consts::{FRAC_PI_2, PI},
+    sync::Arc,
+};
+
+use rand::Rng;
+
+use crate::{core::point3::Point, utils::math::Axis};
+
+use super::{
+    aabb::{self, Aabb},
+    light::Light,
+};
+
+// set of directions around w with half angle acos(cos_theta)
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionCone {
+    pub w: Point,
+    pub cos_theta: f64,
+}
+
+impl DirectionCone {
+    #[must_use]
+    pub fn new(w: Point, cos_theta: f64) -> Self {
+        Self {
+            w: w.unit(),
+            cos_theta,
+        }
+    }
+
+    #[must_use]
+    pub fn entire_sphere() -> Self {
+        Self {
+            w: Point::new(0.0, 0.0, 1.0),
+            cos_theta: -1.0,
+        }
+    }
+
+    // smallest cone containing both
+    fn union(a: &DirectionCone, b: &DirectionCone) -> DirectionCone {
+        let theta_a = safe_acos(a.cos_theta);
+        let theta_b = safe_acos(b.cos_theta);
+        let theta_d = angle_between(&a.w, &b.w);
+
+        if f64::min(theta_d + theta_b, PI) <= theta_a {
+            return *a;
+        }
+        if f64::min(theta_d + theta_a, PI) <= theta_b {
+            return *b;
+        }
+
+        let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+        if theta_o >= PI {
+            return DirectionCone::entire_sphere();
+        }
+
+        // rotate a.w towards b.w, so the new axis is in the middle of the spread
+        let theta_r = theta_o - theta_a;
+        let wr = a.w.cross(&b.w);
+        if wr.near_zero() {
+            return DirectionCone::entire_sphere();
+        }
+        DirectionCone {
+            w: rotate(&a.w, &wr.unit(), theta_r),
+            cos_theta: theta_o.cos(),
+        }
+    }
+}
+
+// Spatial and directional bounds of emission together with emitted power,
+// enough to estimate how much a light (or a cluster of lights) may contribute to a point.
+// Lights emit in directions of cone (w, theta_o) and the emission may spread
+// up to theta_e further from its boundary (e.g. pi/2 for diffuse surfaces)
+#[derive(Clone, Copy, Debug)]
+pub struct LightBounds {
+    pub bbox: Aabb,
+    pub phi: f64,
+    pub cone: DirectionCone,
+    pub cos_theta_e: f64,
+}
+
+impl LightBounds {
+    fn merge(lhs: &LightBounds, rhs: &LightBounds) -> LightBounds {
+        if lhs.phi == 0.0 {
+            return *rhs;
+        }
+        if rhs.phi == 0.0 {
+            return *lhs;
+        }
+        LightBounds {
+            bbox: lhs.bbox.expand(&rhs.bbox),
+            phi: lhs.phi + rhs.phi,
+            cone: DirectionCone::union(&lhs.cone, &rhs.cone),
+            cos_theta_e: f64::min(lhs.cos_theta_e, rhs.cos_theta_e),
+        }
+    }
+
+    fn centroid(&self) -> Point {
+        Point::new(
+            (self.bbox.x.min + self.bbox.x.max) * 0.5,
+            (self.bbox.y.min + self.bbox.y.max) * 0.5,
+            (self.bbox.z.min + self.bbox.z.max) * 0.5,
+        )
+    }
+
+    // conservative estimate of contribution at point p with surface normal n
+    // (None for points inside media, which scatter in all directions)
+    #[must_use]
+    pub fn importance(&self, p: &Point, n: Option<&Point>) -> f64 {
+        let pc = self.centroid();
+        let diag = Point::new(self.bbox.x.size(), self.bbox.y.size(), self.bbox.z.size());
+        let r = diag.size() * 0.5;
+        let to_p = *p - pc;
+        let d2 = f64::max(to_p.scalar_prod(&to_p), r * r * 0.25);
+        let d = d2.sqrt();
+        if d == 0.0 {
+            return self.phi;
+        }
+        let wi = to_p / d;
+
+        // angle between emission axis and direction to p
+        let cos_w = self.cone.w.scalar_prod(&wi);
+        let sin_w = safe_sqrt(1.0 - cos_w * cos_w);
+
+        // angle subtended by bounds from p
+        let (sin_b, cos_b) = if self.contains(p) || d <= r {
+            (0.0, -1.0)
+        } else {
+            let sin2 = r * r / d2;
+            (sin2.sqrt(), safe_sqrt(1.0 - sin2))
+        };
+
+        // cos of max(0, theta_w - theta_o - theta_b)
+        let cos_o = self.cone.cos_theta;
+        let sin_o = safe_sqrt(1.0 - cos_o * cos_o);
+        let (sin_x, cos_x) = sub_clamped(sin_w, cos_w, sin_o, cos_o);
+        let (_, cos_p) = sub_clamped(sin_x, cos_x, sin_b, cos_b);
+        if cos_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.phi * cos_p / d2;
+        if let Some(n) = n {
+            let cos_i = wi.scalar_prod(n).abs();
+            let sin_i = safe_sqrt(1.0 - cos_i * cos_i);
+            let (_, cos_pi) = sub_clamped(sin_i, cos_i, sin_b, cos_b);
+            importance *= cos_pi;
+        }
+        f64::max(importance, 0.0)
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        self.bbox.x.contains(p.x()) && self.bbox.y.contains(p.y()) && self.bbox.z.contains(p.z())
+    }
+}
+
+enum LightNode {
+    Leaf {
+        light: usize,
+        bounds: LightBounds,
+    },
+    Interior {
+        left: Box<LightNode>,
+        right: Box<LightNode>,
+        bounds: LightBounds,
+    },
+}
+
+impl LightNode {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            LightNode::Leaf { bounds, .. } | LightNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+// Light acceleration structure which picks lights proportional to their estimated
+// contribution to the shading point. Lights without bounds (infinitely far, like
+// directional) can't be placed in the tree and are picked uniformly next to it.
+pub struct LightBvh {
+    lights: Vec<Arc<dyn Light>>,
+    infinite: Vec<usize>,
+    root: Option<LightNode>,
+}
+
+impl LightBvh {
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut infinite = Vec::new();
+        let mut bounded = Vec::new();
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.phi > 0.0 => bounded.push((i, bounds)),
+                Some(_) => {}
+                None => infinite.push(i),
+            }
+        }
+
+        let root = (!bounded.is_empty()).then(|| Self::build(&mut bounded));
+        Self {
+            lights: lights.to_vec(),
+            infinite,
+            root,
+        }
+    }
+
+    fn build(lights: &mut [(usize, LightBounds)]) -> LightNode {
+        if let [(light, bounds)] = lights {
+            return LightNode::Leaf {
+                light: *light,
+                bounds: *bounds,
+            };
+        }
+
+        let mut centroids = aabb::EMPTY;
+        for (_, bounds) in lights.iter() {
+            let c = bounds.centroid();
+            centroids = centroids.expand(&Aabb::from_points(&c, &c));
+        }
+        let axis = centroids.longest_axis();
+        lights.sort_unstable_by(Self::cmp(axis));
+
+        let mid = lights.len() / 2;
+        let (lhs, rhs) = lights.split_at_mut(mid);
+        let left = Self::build(lhs);
+        let right = Self::build(rhs);
+        let bounds = LightBounds::merge(left.bounds(), right.bounds());
+        LightNode::Interior {
+            left: Box::new(left),
+            right: Box::new(right),
+            bounds,
+        }
+    }
+
+    fn cmp(axis: Axis) -> impl FnMut(&(usize, LightBounds), &(usize, LightBounds)) -> Ordering {
+        move |lhs, rhs| {
+            let (lc, rc) = (lhs.1.centroid(), rhs.1.centroid());
+            lc.coord(axis)
+                .partial_cmp(&rc.coord(axis))
+                .expect("light bounds contain NaN which is impossible to compare")
+        }
+    }
+
+    // picks one light for shading point p, returns it with the probability it was picked with
+    pub fn sample(
+        &self,
+        p: &Point,
+        n: Option<&Point>,
+        rng: &mut impl Rng,
+    ) -> Option<(&Arc<dyn Light>, f64)> {
+        if self.infinite.is_empty() && self.root.is_none() {
+            return None;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let n_infinite = self.infinite.len() as f64;
+        // the whole tree competes as one more light, so with n directional lights each of
+        // them gets 1/(n+1) and the tree n/(n+1) regardless of power. Unbiased either way,
+        // but a dim sun next to many bright bounded lights takes a share it doesn't deserve
+        let p_infinite = if self.root.is_some() {
+            n_infinite / (n_infinite + 1.0)
+        } else {
+            1.0
+        };
+
+        if rng.random::<f64>() < p_infinite {
+            let i = rng.random_range(0..self.infinite.len());
+            return Some((&self.lights[self.infinite[i]], p_infinite / n_infinite));
+        }
+
+        let mut pmf = 1.0 - p_infinite;
+        let mut node = self.root.as_ref()?;
+        loop {
+            match node {
+                LightNode::Leaf { light, bounds } => {
+                    return (bounds.importance(p, n) > 0.0).then(|| (&self.lights[*light], pmf));
+                }
+                LightNode::Interior { left, right, .. } => {
+                    let li = left.bounds().importance(p, n);
+                    let ri = right.bounds().importance(p, n);
+                    if li == 0.0 && ri == 0.0 {
+                        return None;
+                    }
+                    let p_left = li / (li + ri);
+                    if rng.random::<f64>() < p_left {
+                        pmf *= p_left;
+                        node = left;
+                    } else {
+                        pmf *= 1.0 - p_left;
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    f64::sqrt(f64::max(x, 0.0))
+}
+
+fn safe_acos(x: f64) -> f64 {
+    x.clamp(-1.0, 1.0).acos()
+}
+
+fn angle_between(a: &Point, b: &Point) -> f64 {
+    safe_acos(a.scalar_prod(b))
+}
+
+// (sin, cos) of max(0, theta_a - theta_b)
+fn sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> (f64, f64) {
+    if cos_a > cos_b {
+        (0.0, 1.0)
+    } else {
+        (sin_a * cos_b - cos_a * sin_b, cos_a * cos_b + sin_a * sin_b)
+    }
+}
+
+// rodrigues rotation of v around unit axis by theta
+fn rotate(v: &Point, axis: &Point, theta: f64) -> Point {
+    let (sin, cos) = theta.sin_cos();
+    *v * cos + axis.cross(v) * sin + *axis * (axis.scalar_prod(v) * (1.0 - cos))
+}
+
+// emission of a point or spherical emitter: all directions, no falloff over the hemisphere
+#[must_use]
+pub fn omnidirectional(bbox: Aabb, phi: f64) -> LightBounds {
+    LightBounds {
+        bbox,
+        phi,
+        cone: DirectionCone::entire_sphere(),
+        cos_theta_e: FRAC_PI_2.cos(),
+    }
+}
+
+#[test]
+fn test_light_bvh_prefers_near_lights() {
+    use super::light::PointLight;
+    use crate::core::rgb::ARgb;
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    let near: Arc<dyn Light> = Arc::new(PointLight::new(
+        Point::new(0.0, 1.0, 0.0),
+        ARgb::new(1.0, 1.0, 1.0),
+    ));
+    let far: Arc<dyn Light> = Arc::new(PointLight::new(
+        Point::new(0.0, 10.0, 0.0),
+        ARgb::new(1.0, 1.0, 1.0),
+    ));
+    let lights = [Arc::clone(&near), far];
+    let bvh = LightBvh::new(&lights);
+
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(3);
+    let p = Point::default();
+    let n = Point::new(0.0, 1.0, 0.0);
+    let mut pmf_sum = 0.0;
+    let mut near_count = 0;
+    for _ in 0..1000 {
+        let (light, pmf) = bvh
+            .sample(&p, Some(&n), &mut rng)
+            .expect("both lights are visible");
+        if Arc::ptr_eq(light, &near) {
+            near_count += 1;
+            pmf_sum += pmf;
+        }
+    }
+    assert!(near_count > 900);
+    assert!(pmf_sum / f64::from(near_count) > 0.9);
+    assert_pmfs_match_frequencies(&bvh, &lights, &p, &n);
+}
+
+#[test]
+fn test_light_bvh_splits_between_directional_and_bounded_lights() {
+    use super::light::{DirectionalLight, PointLight};
+    use crate::core::rgb::ARgb;
+
+    let white = ARgb::new(1.0, 1.0, 1.0);
+    let point =
+        |y: f64| -> Arc<dyn Light> { Arc::new(PointLight::new(Point::new(0.0, y, 0.0), white)) };
+    let sun = |x: f64| -> Arc<dyn Light> {
+        Arc::new(DirectionalLight::new(Point::new(x, -1.0, 0.0), white))
+    };
+    let lights = [sun(0.0), point(1.0), point(3.0), sun(0.5), point(10.0)];
+    let bvh = LightBvh::new(&lights);
+
+    let p = Point::default();
+    let n = Point::new(0.0, 1.0, 0.0);
+    let pmfs = assert_pmfs_match_frequencies(&bvh, &lights, &p, &n);
+    // two directional lights: 1/3 each, the tree of bounded lights shares the last third
+    for i in [0, 3] {
+        assert_approx_eq::assert_approx_eq!(pmfs[i], 1.0 / 3.0);
+    }
+    let bounded: f64 = [1, 2, 4].iter().map(|&i| pmfs[i]).sum();
+    assert_approx_eq::assert_approx_eq!(bounded, 1.0 / 3.0);
+    assert!(pmfs[1] > pmfs[2] && pmfs[2] > pmfs[4]);
+}
+
+// the pmf sample returns for each light must be how often that light is actually picked
+#[cfg(test)]
+fn assert_pmfs_match_frequencies(
+    bvh: &LightBvh,
+    lights: &[Arc<dyn Light>],
+    p: &Point,
+    n: &Point,
+) -> Vec<f64> {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    const COUNT: u32 = 100_000;
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(7);
+    let mut picks = vec![0_u32; lights.len()];
+    let mut pmfs = vec![0.0; lights.len()];
+    for _ in 0..COUNT {
+        let (light, pmf) = bvh
+            .sample(p, Some(n), &mut rng)
+            .expect("all lights are visible");
+        let i = lights
+            .iter()
+            .position(|l| Arc::ptr_eq(l, light))
+            .expect("sampled light is one of the lights");
+        picks[i] += 1;
+        pmfs[i] = pmf;
+    }
+    for (count, pmf) in picks.iter().zip(&pmfs) {
+        let frequency = f64::from(*count) / f64::from(COUNT);
+        assert!(
+            (frequency - pmf).abs() < 0.01,
+            "picked {frequency} of the time, pmf {pmf}"
+        );
+    }
+    pmfs
+}
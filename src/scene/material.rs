@@ -1,19 +1,34 @@
-use std::sync::Arc;
+use std::{f64::consts::FRAC_1_PI, sync::Arc};
 
 use rand::{distr::Uniform, prelude::Distribution, Rng, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
 
 use crate::core::{
+    onb::Onb,
     point3::{Point, MIN_FLOAT_64_PRECISION},
-    ray::Ray,
+    ray::{MediumEntry, MediumStack, Ray},
     rgb::ARgb,
 };
 
 use super::{
     hittable::{HitRec, NormalFace},
+    microfacet::{self, TrowbridgeReitz},
+    noise::{splitmix64, unit_floats},
     texture::{SolidColor, Texture},
 };
 
+// how a material seen by the camera is composited, see Integrator
+#[derive(Clone, Copy, Debug)]
+pub enum Matte {
+    // transparent hole in the render, hides whatever is behind it
+    Holdout,
+    // transparent except for the shadows and reflections it receives
+    ShadowCatcher,
+    // lets the camera ray through with the weight of scatter, coverage comes from what
+    // is behind (e.g. where a coloured medium tints rays leaving it)
+    Transparent,
+}
+
 pub trait Material: Send + Sync {
     fn scatter(
         &self,
@@ -24,6 +39,33 @@ pub trait Material: Send + Sync {
     ) -> bool {
         false
     }
+
+    // scattering function times cosine between normal and wi (unit, towards the light),
+    // used for explicit light sampling. None means the material can't be lit by a given
+    // direction (e.g. perfect mirrors), so only scattered rays carry its light.
+    fn eval(&self, _r_in: &Ray, _wi: &Point, _hr: &HitRec) -> Option<ARgb> {
+        None
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point) -> ARgb {
+        ARgb::default()
+    }
+
+    // true for emitters which are also registered as scene lights: their emission reaches
+    // diffuse surfaces through light sampling, so scattered rays must not count it twice
+    fn is_sampled_light(&self) -> bool {
+        false
+    }
+
+    // participating media scatter in all directions from a point inside a volume,
+    // there is no surface normal to take into account when picking lights
+    fn is_volumetric(&self) -> bool {
+        false
+    }
+
+    fn matte(&self) -> Option<Matte> {
+        None
+    }
 }
 
 pub struct Lambertian {
@@ -33,6 +75,8 @@ pub struct Lambertian {
 }
 
 impl Lambertian {
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
     pub fn new(albedo: ARgb, reflectance: f64) -> Self {
         let between = Uniform::new(0.0, 1.0 + MIN_FLOAT_64_PRECISION)
             .expect("constants should not cause panic in any universe");
@@ -44,12 +88,13 @@ impl Lambertian {
         }
     }
 
+    #[allow(clippy::missing_panics_doc)]
     pub fn with_texture(texture: &Arc<dyn Texture>, reflectance: f64) -> Self {
         let between = Uniform::new(0.0, 1.0 + MIN_FLOAT_64_PRECISION)
             .expect("constants should not cause panic in any universe");
 
         Lambertian {
-            texture: Arc::clone(&texture),
+            texture: Arc::clone(texture),
             reflectance,
             between,
         }
@@ -73,7 +118,7 @@ impl Material for Lambertian {
             if scatter_dir.near_zero() {
                 scatter_dir = hr.n;
             }
-            *scattered = Ray::new(hr.p, scatter_dir, Some(r_in.time()));
+            *scattered = r_in.continued(hr.p, scatter_dir);
             *attenuation =
                 self.texture.color(hr.tx_coord.u, hr.tx_coord.v, &hr.p) / self.reflectance;
             true
@@ -81,6 +126,128 @@ impl Material for Lambertian {
             false
         }
     }
+
+    fn eval(&self, _r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let cos = wi.scalar_prod(&hr.n);
+        (cos > 0.0)
+            .then(|| self.texture.color(hr.tx_coord.u, hr.tx_coord.v, &hr.p) * (cos * FRAC_1_PI))
+    }
+}
+
+// Rough diffuse surface (clay, concrete, the moon): lambertian microfacets with
+// gaussian distributed slopes, sigma is their standard deviation in radians.
+// Qualitative model of Oren and Nayar, sigma 0 is Lambertian.
+pub struct OrenNayar {
+    texture: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    #[must_use]
+    pub fn new(albedo: ARgb, sigma: f64) -> Self {
+        let texture: Arc<dyn Texture> = Arc::new(SolidColor::new(albedo));
+        Self::with_texture(&texture, sigma)
+    }
+
+    pub fn with_texture(texture: &Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma2 = sigma * sigma;
+        Self {
+            texture: Arc::clone(texture),
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    // brdf relative to lambertian, wo and wi are unit and above the surface
+    fn factor(&self, wo: &Point, wi: &Point, n: &Point) -> f64 {
+        let cos_o = wo.scalar_prod(n);
+        let cos_i = wi.scalar_prod(n);
+        let sin_o = f64::sqrt(f64::max(0.0, 1.0 - cos_o * cos_o));
+        let sin_i = f64::sqrt(f64::max(0.0, 1.0 - cos_i * cos_i));
+        if sin_o < 1e-4 || sin_i < 1e-4 {
+            return self.a;
+        }
+        // cosine of the azimuth difference times both sines
+        let tangent_prod = (*wo - *n * cos_o).scalar_prod(&(*wi - *n * cos_i));
+        // alpha is the larger of both angles, beta the smaller one
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o)
+        };
+        self.a + self.b * f64::max(0.0, tangent_prod / (sin_o * sin_i)) * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        // cosine sampling, the lambertian part of the weight cancels out
+        let wi = Point::random_cosine_on_hemisphere(&mut rand::rng(), &hr.n);
+        if wi.scalar_prod(&hr.n) <= 0.0 {
+            return false;
+        }
+        *scattered = r_in.continued(hr.p, wi);
+        *attenuation = self.texture.color(hr.tx_coord.u, hr.tx_coord.v, &hr.p)
+            * self.factor(&-r_in.dir().unit(), &wi, &hr.n);
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let cos = wi.scalar_prod(&hr.n);
+        let wo = -r_in.dir().unit();
+        (cos > 0.0 && wo.scalar_prod(&hr.n) > 0.0).then(|| {
+            self.texture.color(hr.tx_coord.u, hr.tx_coord.v, &hr.p)
+                * (self.factor(&wo, wi, &hr.n) * cos * FRAC_1_PI)
+        })
+    }
+}
+
+pub struct DiffuseLight {
+    texture: Arc<dyn Texture>,
+    sampled: bool,
+}
+
+impl DiffuseLight {
+    #[must_use]
+    pub fn new(emit: ARgb) -> Self {
+        Self {
+            texture: Arc::new(SolidColor::new(emit)),
+            sampled: false,
+        }
+    }
+
+    pub fn with_texture(texture: &Arc<dyn Texture>) -> Self {
+        Self {
+            texture: Arc::clone(texture),
+            sampled: false,
+        }
+    }
+
+    // emitter which is also present in scene lights
+    #[must_use]
+    pub fn sampled(emit: ARgb) -> Self {
+        Self {
+            sampled: true,
+            ..Self::new(emit)
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.texture.color(u, v, p)
+    }
+
+    fn is_sampled_light(&self) -> bool {
+        self.sampled
+    }
 }
 
 pub struct Metal {
@@ -89,6 +256,7 @@ pub struct Metal {
 }
 
 impl Metal {
+    #[must_use]
     pub fn new(albedo: ARgb, fuzz: Option<f64>) -> Self {
         Metal { albedo, fuzz }
     }
@@ -107,19 +275,411 @@ impl Material for Metal {
             let mut rng = rand::rng();
             reflected = reflected.unit() + (Point::random_unit_on_sphere(&mut rng) * fuzz);
         }
-        *scattered = Ray::new(hr.p, reflected, Some(r_in.time()));
+        *scattered = r_in.continued(hr.p, reflected);
         *attenuation = self.albedo;
         self.fuzz.is_none() || scattered.dir().scalar_prod(&hr.n) > 0.0
     }
 }
 
+// Blend of two materials (rust over metal, moss over stone). Mask (or constant weight)
+// is the fraction of second, every scattering event picks one of them at random,
+// so neither has to know about blending.
+pub struct MixMaterial {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    mask: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(first: &Arc<dyn Material>, second: &Arc<dyn Material>, weight: f64) -> Self {
+        let mask: Arc<dyn Texture> = Arc::new(SolidColor::new(ARgb::new(weight, weight, weight)));
+        Self::with_mask(first, second, &mask)
+    }
+
+    // scalar weight is the luminance of the mask
+    pub fn with_mask(
+        first: &Arc<dyn Material>,
+        second: &Arc<dyn Material>,
+        mask: &Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            first: Arc::clone(first),
+            second: Arc::clone(second),
+            mask: Arc::clone(mask),
+        }
+    }
+
+    fn weight(&self, u: f64, v: f64, p: &Point) -> f64 {
+        self.mask.color(u, v, p).luminance().clamp(0.0, 1.0)
+    }
+
+    // The pick is hashed from the hit, so scatter and eval of one hit agree on it. When
+    // only one side can be evaluated (lambertian over metal), light sampling then covers
+    // exactly the hits whose scattered ray took that side.
+    fn picks_second(&self, r_in: &Ray, hr: &HitRec) -> bool {
+        let hash =
+            hr.p.e
+                .iter()
+                .chain(r_in.dir().e.iter())
+                .fold(0, |h, c| splitmix64(h ^ c.to_bits()));
+        unit_floats(hash)[0] < self.weight(hr.tx_coord.u, hr.tx_coord.v, &hr.p)
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let picked = if self.picks_second(r_in, hr) {
+            &self.second
+        } else {
+            &self.first
+        };
+        picked.scatter(r_in, attenuation, scattered, hr)
+    }
+
+    // weighted sum when both sides can be evaluated, otherwise the side picked for this
+    // hit: unweighted, as it is picked with probability of its weight
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let weight = self.weight(hr.tx_coord.u, hr.tx_coord.v, &hr.p);
+        match (
+            self.first.eval(r_in, wi, hr),
+            self.second.eval(r_in, wi, hr),
+        ) {
+            (Some(first), Some(second)) => Some(first * (1.0 - weight) + second * weight),
+            (first, second) => {
+                if self.picks_second(r_in, hr) {
+                    second
+                } else {
+                    first
+                }
+            }
+        }
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        let weight = self.weight(u, v, p);
+        self.first.emitted(u, v, p) * (1.0 - weight) + self.second.emitted(u, v, p) * weight
+    }
+
+    fn is_sampled_light(&self) -> bool {
+        self.first.is_sampled_light() || self.second.is_sampled_light()
+    }
+}
+
+// Different materials on both sides of a surface (paper, leaf and playing cards).
+// Front is the side the outward normal of the primitive points to, both materials get
+// the normal facing the ray. Emission has no side, it is taken from the front.
+pub struct TwoSided {
+    front: Arc<dyn Material>,
+    back: Arc<dyn Material>,
+}
+
+impl TwoSided {
+    pub fn new(front: &Arc<dyn Material>, back: &Arc<dyn Material>) -> Self {
+        Self {
+            front: Arc::clone(front),
+            back: Arc::clone(back),
+        }
+    }
+
+    fn side(&self, hr: &HitRec) -> &Arc<dyn Material> {
+        match hr.face {
+            NormalFace::Outside => &self.front,
+            NormalFace::Inside => &self.back,
+        }
+    }
+}
+
+impl Material for TwoSided {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        self.side(hr).scatter(r_in, attenuation, scattered, hr)
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        self.side(hr).eval(r_in, wi, hr)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.front.emitted(u, v, p)
+    }
+
+    fn is_sampled_light(&self) -> bool {
+        self.front.is_sampled_light()
+    }
+}
+
+// Stands in for a real object of a photograph (a table, an actor): renders transparent
+// and black in reflections, objects behind it are hidden.
+pub struct Holdout;
+
+impl Material for Holdout {
+    fn matte(&self) -> Option<Matte> {
+        Some(Matte::Holdout)
+    }
+}
+
+// Stand-in for the ground of a photograph. The camera sees only shadows and reflections
+// cast on it, to other surfaces it is diffuse with the albedo of the photographed ground.
+pub struct ShadowCatcher {
+    ground: Lambertian,
+}
+
+impl ShadowCatcher {
+    #[must_use]
+    pub fn new(albedo: ARgb) -> Self {
+        Self {
+            ground: Lambertian::new(albedo, 1.0),
+        }
+    }
+}
+
+impl Material for ShadowCatcher {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        self.ground.scatter(r_in, attenuation, scattered, hr)
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        self.ground.eval(r_in, wi, hr)
+    }
+
+    fn matte(&self) -> Option<Matte> {
+        Some(Matte::ShadowCatcher)
+    }
+}
+
+// measured complex ior (eta + i*k) of common metals at red, green and blue wavelengths
+#[derive(Clone, Copy, Debug)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl ConductorPreset {
+    #[must_use]
+    pub fn eta_k(self) -> (ARgb, ARgb) {
+        match self {
+            ConductorPreset::Gold => (
+                ARgb::new(0.143, 0.374, 1.442),
+                ARgb::new(3.983, 2.385, 1.603),
+            ),
+            ConductorPreset::Copper => (
+                ARgb::new(0.200, 0.924, 1.102),
+                ARgb::new(3.912, 2.452, 2.142),
+            ),
+            ConductorPreset::Aluminium => (
+                ARgb::new(1.657, 0.880, 0.521),
+                ARgb::new(9.224, 6.270, 4.837),
+            ),
+            ConductorPreset::Silver => (
+                ARgb::new(0.155, 0.117, 0.138),
+                ARgb::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+// Microfacet metal: GGX distribution with visible normal sampling and exact fresnel
+// from complex ior. Unlike Metal::fuzz it is energy conserving (up to multiple scattering
+// between microfacets, which is ignored).
+pub struct Conductor {
+    eta: ARgb,
+    k: ARgb,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    #[must_use]
+    pub fn new(eta: ARgb, k: ARgb, roughness: f64, anisotropy: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::from_roughness(roughness, anisotropy),
+        }
+    }
+
+    #[must_use]
+    pub fn from_preset(preset: ConductorPreset, roughness: f64, anisotropy: f64) -> Self {
+        let (eta, k) = preset.eta_k();
+        Self::new(eta, k, roughness, anisotropy)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let onb = hr.shading_frame();
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if self.distribution.effectively_smooth() {
+            let wi = Point::new(-wo.x(), -wo.y(), wo.z());
+            *scattered = r_in.continued(hr.p, onb.local_to_world(&wi));
+            *attenuation = microfacet::fresnel_conductor(wo.z(), &self.eta, &self.k);
+            return true;
+        }
+
+        let wm = self.distribution.sample_wm(&wo, &mut rand::rng());
+        let wi = microfacet::reflect(&wo, &wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+        *scattered = r_in.continued(hr.p, onb.local_to_world(&wi));
+        *attenuation = microfacet::fresnel_conductor(wo.scalar_prod(&wm), &self.eta, &self.k)
+            * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo));
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let onb = hr.shading_frame();
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Some(ARgb::default());
+        }
+        let wm = (wo + wi).unit();
+        let f = microfacet::fresnel_conductor(wo.scalar_prod(&wm), &self.eta, &self.k);
+        // cos of wi cancels with the one in the denominator of the brdf
+        Some(f * (self.distribution.d(&wm) * self.distribution.g(&wo, &wi) / (4.0 * wo.z())))
+    }
+}
+
 pub struct Dielectric {
     refraction_index: f64,
+    // beer-lambert absorption coefficient per unit of distance travelled inside
+    absorption: Option<ARgb>,
+    priority: u32,
 }
 
 impl Dielectric {
+    #[must_use]
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            absorption: None,
+            priority: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_absorption(refraction_index: f64, absorption: ARgb) -> Self {
+        Self {
+            absorption: Some(absorption),
+            ..Self::new(refraction_index)
+        }
+    }
+
+    // absorption given as colour of light after travelling distance inside,
+    // easier to pick than the coefficient itself (e.g. green bottle glass)
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn with_transmittance(refraction_index: f64, color: ARgb, distance: f64) -> Self {
+        assert!(
+            distance > 0.0 && distance.is_finite(),
+            "transmittance distance must be positive and finite, got {distance}"
+        );
+        let absorption = color.map(|c| -f64::ln(c.clamp(1e-6, 1.0)) / distance);
+        Self::with_absorption(refraction_index, absorption)
+    }
+
+    // Where volumes overlap (liquid touching walls of a glass, ice in water) the one with
+    // higher priority fills the overlap, surfaces of the others are ignored there.
+    // Modelling liquid slightly bigger than the glass cavity with priority above the glass
+    // gives the correct water-glass interface.
+    #[must_use]
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn entry(&self) -> MediumEntry {
+        MediumEntry {
+            id: std::ptr::from_ref(self).addr(),
+            priority: self.priority,
+            ior: self.refraction_index,
+            absorption: self.absorption,
+        }
+    }
+}
+
+// Which side of a dielectric surface the ray goes to, resolved against the media
+// the ray travels through
+pub struct Crossing {
+    // absorption up to the surface of rays which are inside without having entered, the
+    // integrator attenuates the rest by the media they carry (see Ray::transmittance)
+    pub attenuation: ARgb,
+    // media of the ray after refraction, reflected rays keep their own
+    pub next: MediumStack,
+    // (eta_i, eta_t) or None if the surface is not an interface, because it is
+    // inside of a higher priority volume
+    pub etas: Option<(f64, f64)>,
+}
+
+impl Crossing {
+    #[must_use]
+    pub fn resolve(this: MediumEntry, r_in: &Ray, hr: &HitRec) -> Self {
+        let media = r_in.media();
+        // rays which didn't enter through our surface (e.g. camera inside) still know
+        // from the face that they are inside
+        let untracked_inside = matches!(hr.face, NormalFace::Inside) && !media.contains(this.id);
+        let current = if untracked_inside {
+            Some(&this)
+        } else {
+            media.current()
+        };
+
+        let attenuation = if untracked_inside {
+            this.transmittance(hr.t * r_in.dir().size())
+        } else {
+            ARgb::new(1.0, 1.0, 1.0)
+        };
+
+        let mut next = *media;
+        let dominant = |m: &MediumStack| m.current().is_some_and(|m| m.id == this.id);
+        let etas = match hr.face {
+            NormalFace::Outside => {
+                next.push(this);
+                dominant(&next).then(|| (current.map_or(1.0, |m| m.ior), this.ior))
+            }
+            NormalFace::Inside => {
+                let was_dominant = untracked_inside || dominant(media);
+                next.remove(this.id);
+                was_dominant.then(|| (this.ior, next.current().map_or(1.0, |m| m.ior)))
+            }
+        };
+        Self {
+            attenuation,
+            next,
+            etas,
+        }
     }
 }
 
@@ -131,26 +691,170 @@ impl Material for Dielectric {
         scattered: &mut Ray,
         hr: &HitRec,
     ) -> bool {
-        *attenuation = ARgb::new(1.0, 1.0, 1.0);
-        let refraction_index = match hr.face {
-            NormalFace::Inside => self.refraction_index,
-            NormalFace::Outside => 1.0 / self.refraction_index,
+        let crossing = Crossing::resolve(self.entry(), r_in, hr);
+        *attenuation = crossing.attenuation;
+        let Some((eta_i, eta_t)) = crossing.etas else {
+            *scattered = r_in.continued(hr.p, r_in.dir()).with_media(&crossing.next);
+            return true;
         };
+
+        let refraction_index = eta_i / eta_t;
         let unit_dir = r_in.dir().unit();
         let cos_theta = f64::min((-unit_dir).scalar_prod(&hr.n), 1.0);
         let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
         let mut rng = rand::rng();
-        let direction = if refraction_index * sin_theta > 1.0
+        *scattered = if refraction_index * sin_theta > 1.0
             || reflectance(cos_theta, refraction_index) > rng.random::<f64>()
         {
-            reflect(&unit_dir, &hr.n)
+            // reflected ray stays in the same media
+            r_in.continued(hr.p, reflect(&unit_dir, &hr.n))
+        } else {
+            r_in.continued(hr.p, refract(&unit_dir, &hr.n, refraction_index))
+                .with_media(&crossing.next)
+        };
+        true
+    }
+}
+
+// Microfacet glass (frosted glass, rough plastics): GGX reflection and transmission
+// with exact dielectric fresnel. Roughness may vary over the surface with a grey texture.
+// Shares medium stack handling (priorities, absorption) with Dielectric.
+pub struct RoughDielectric {
+    refraction_index: f64,
+    roughness: Arc<dyn Texture>,
+    absorption: Option<ARgb>,
+    priority: u32,
+}
+
+impl RoughDielectric {
+    #[must_use]
+    pub fn new(refraction_index: f64, roughness: f64) -> Self {
+        let roughness: Arc<dyn Texture> =
+            Arc::new(SolidColor::new(ARgb::new(roughness, roughness, roughness)));
+        Self::with_roughness_texture(refraction_index, &roughness)
+    }
+
+    pub fn with_roughness_texture(refraction_index: f64, roughness: &Arc<dyn Texture>) -> Self {
+        Self {
+            refraction_index,
+            roughness: Arc::clone(roughness),
+            absorption: None,
+            priority: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_absorption(mut self, absorption: ARgb) -> Self {
+        self.absorption = Some(absorption);
+        self
+    }
+
+    #[must_use]
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn entry(&self) -> MediumEntry {
+        MediumEntry {
+            id: std::ptr::from_ref(self).addr(),
+            priority: self.priority,
+            ior: self.refraction_index,
+            absorption: self.absorption,
+        }
+    }
+
+    fn distribution(&self, hr: &HitRec) -> TrowbridgeReitz {
+        let roughness = self
+            .roughness
+            .color(hr.tx_coord.u, hr.tx_coord.v, &hr.p)
+            .luminance();
+        TrowbridgeReitz::from_roughness(roughness, 0.0)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let crossing = Crossing::resolve(self.entry(), r_in, hr);
+        *attenuation = crossing.attenuation;
+        let Some((eta_i, eta_t)) = crossing.etas else {
+            *scattered = r_in.continued(hr.p, r_in.dir()).with_media(&crossing.next);
+            return true;
+        };
+
+        // hit normal always faces the incoming ray, so wo is in the upper hemisphere
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let distribution = self.distribution(hr);
+        let mut rng = rand::rng();
+        let smooth = distribution.effectively_smooth();
+        let wm = if smooth {
+            Point::new(0.0, 0.0, 1.0)
         } else {
-            refract(&unit_dir, &hr.n, refraction_index)
+            distribution.sample_wm(&wo, &mut rng)
         };
 
-        *scattered = Ray::new(hr.p, direction, Some(r_in.time()));
+        // fresnel is the probability of reflection, so it cancels out of the weight,
+        // which is G / G1 for visible normal sampling of both lobes
+        let eta = eta_t / eta_i;
+        let cos_o = wo.scalar_prod(&wm);
+        let transmitted = microfacet::refract(&wo, &wm, eta)
+            .filter(|_| rng.random::<f64>() >= microfacet::fresnel_dielectric(cos_o, eta));
+        let (wi, next) = match transmitted {
+            Some(wi) if wi.z() < 0.0 => (wi, Some(&crossing.next)),
+            Some(_) => return false,
+            None => {
+                let wi = microfacet::reflect(&wo, &wm);
+                if wi.z() <= 0.0 {
+                    return false;
+                }
+                (wi, None)
+            }
+        };
+
+        if !smooth {
+            *attenuation = *attenuation * (distribution.g(&wo, &wi) / distribution.g1(&wo));
+        }
+        let ray = r_in.continued(hr.p, onb.local_to_world(&wi));
+        *scattered = match next {
+            Some(media) => ray.with_media(media),
+            None => ray,
+        };
         true
     }
+
+    // Only the reflection lobe is lit explicitly: shadow rays towards the other side
+    // would be blocked by the surface itself, so transmitted rays carry emission instead
+    // (None for those directions keeps the integrator counting it).
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let distribution = self.distribution(hr);
+        if distribution.effectively_smooth() {
+            return None;
+        }
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let crossing = Crossing::resolve(self.entry(), r_in, hr);
+        let (eta_i, eta_t) = crossing.etas?;
+        let wm = (wo + wi).unit();
+        let f = microfacet::fresnel_dielectric(wo.scalar_prod(&wm), eta_t / eta_i);
+        Some(
+            crossing.attenuation
+                * (f * distribution.d(&wm) * distribution.g(&wo, &wi) / (4.0 * wo.z())),
+        )
+    }
 }
 
 // snells law refraction with some math without proof
@@ -175,3 +879,351 @@ fn reflectance(cos: f64, refraction_index: f64) -> f64 {
     let r0 = r0 * r0;
     r0 + (1.0 - r0) * f64::powi(1.0 - cos, 5)
 }
+
+#[test]
+fn test_oren_nayar_is_lambertian_at_zero_sigma() {
+    let albedo = ARgb::new(0.7, 0.4, 0.2);
+    let lambertian = Lambertian::new(albedo, 1.0);
+    let oren_nayar = OrenNayar::new(albedo, 0.0);
+    let n = Point::new(0.0, 0.0, 1.0);
+    let hr = HitRec::new(
+        Point::default(),
+        n,
+        1.0,
+        Arc::new(Lambertian::new(albedo, 1.0)),
+    );
+    let r_in = Ray::new(Point::new(-1.0, 0.0, 1.0), Point::new(1.0, 0.3, -1.0), None);
+    for wi in [
+        Point::new(0.0, 0.0, 1.0),
+        Point::new(0.6, 0.0, 0.8),
+        Point::new(-0.3, 0.9, 0.1).unit(),
+    ] {
+        let expected = lambertian.eval(&r_in, &wi, &hr).expect("lambertian is lit");
+        let actual = oren_nayar.eval(&r_in, &wi, &hr).expect("oren-nayar is lit");
+        for (e, a) in expected.channels().into_iter().zip(actual.channels()) {
+            assert_approx_eq::assert_approx_eq!(e, a);
+        }
+    }
+    // rough surfaces are brighter towards the light at grazing angles than lambertian
+    let rough = OrenNayar::new(albedo, 0.5);
+    let back = Point::new(-0.9, 0.0, 0.1).unit();
+    let expected = lambertian
+        .eval(&r_in, &back, &hr)
+        .expect("lambertian is lit");
+    let actual = rough.eval(&r_in, &back, &hr).expect("oren-nayar is lit");
+    assert!(actual.luminance() > expected.luminance());
+}
+
+#[test]
+fn test_mix_eval_weights_its_components() {
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.8, 0.1, 0.1), 1.0));
+    let blue: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.1, 0.1, 0.8), 1.0));
+    let metal: Arc<dyn Material> = Arc::new(Metal::new(ARgb::new(0.9, 0.9, 0.9), None));
+    let weight = 0.3;
+    let n = Point::new(0.0, 0.0, 1.0);
+    let wi = Point::new(0.6, 0.0, 0.8);
+    let hit_at = |x: f64| HitRec::new(Point::new(x, 0.0, 0.0), n, 1.0, Arc::clone(&red));
+    let r_in = Ray::new(Point::new(-1.0, 0.0, 1.0), Point::new(1.0, 0.3, -1.0), None);
+
+    // both sides evaluable: weighted sum at every hit
+    let diffuse_mix = MixMaterial::new(&red, &blue, weight);
+    let hr = hit_at(0.0);
+    let expected = red.eval(&r_in, &wi, &hr).expect("red is lit") * (1.0 - weight)
+        + blue.eval(&r_in, &wi, &hr).expect("blue is lit") * weight;
+    let actual = diffuse_mix.eval(&r_in, &wi, &hr).expect("mix is lit");
+    for (e, a) in expected.channels().into_iter().zip(actual.channels()) {
+        assert_approx_eq::assert_approx_eq!(e, a);
+    }
+
+    // rust over metal: the diffuse side stays lit on the hits that pick it, and
+    // scatter takes the metal exactly on the others
+    let rusty = MixMaterial::new(&red, &metal, weight);
+    let diffuse = red.eval(&r_in, &wi, &hit_at(0.0)).expect("red is lit");
+    let count = 10_000;
+    let mut lit = 0;
+    for i in 0..count {
+        let hr = hit_at(f64::from(i) * 1e-4);
+        let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+        assert!(rusty.scatter(&r_in, &mut attenuation, &mut scattered, &hr));
+        // lambertian never scatters exactly into the mirror direction
+        let mirror = r_in.dir().unit().reflect(&n);
+        let took_metal = scattered.dir().unit().scalar_prod(&mirror) > 1.0 - 1e-9;
+        match rusty.eval(&r_in, &wi, &hr) {
+            Some(f) => {
+                lit += 1;
+                assert_approx_eq::assert_approx_eq!(f.luminance(), diffuse.luminance());
+                assert!(!took_metal);
+            }
+            None => assert!(took_metal),
+        }
+    }
+    let lit_share = f64::from(lit) / f64::from(count);
+    assert!((lit_share - (1.0 - weight)).abs() < 0.02, "{lit_share}");
+}
+
+#[test]
+fn test_dielectric_transmittance_follows_beer_lambert() {
+    use super::{hittable::Hittable, sphere::Sphere};
+    use crate::utils::interval::Interval;
+
+    // refractive index 1 refracts straight through, the path inside is the diameter 2
+    let color = ARgb::new(0.5, 0.8, 1.0);
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    for (distance, exponent) in [(2.0, 1.0), (4.0, 0.5), (1.0, 2.0)] {
+        let glass: Arc<dyn Material> =
+            Arc::new(Dielectric::with_transmittance(1.0, color, distance));
+        let ball = Sphere::new_static(1.0, Point::default(), glass);
+        let ray = Ray::new(Point::new(0.0, 0.0, -5.0), Point::new(0.0, 0.0, 1.0), None);
+
+        let (mut attenuation, mut inside) = (ARgb::default(), Ray::default());
+        let entry = ball.hit(&ray, &ray_t).expect("ray enters the ball");
+        assert!(entry
+            .mat
+            .scatter(&ray, &mut attenuation, &mut inside, &entry));
+        // nothing absorbed before entering
+        assert_approx_eq::assert_approx_eq!(attenuation.luminance(), 1.0);
+
+        let mut out = Ray::default();
+        let exit = ball.hit(&inside, &ray_t).expect("ray leaves the ball");
+        assert_approx_eq::assert_approx_eq!(exit.t * inside.dir().size(), 2.0);
+        // the way through is absorbed by the integrator, not by the surface
+        assert!(exit.mat.scatter(&inside, &mut attenuation, &mut out, &exit));
+        assert_approx_eq::assert_approx_eq!(attenuation.luminance(), 1.0);
+        let absorbed = inside.transmittance(exit.t);
+        for (a, c) in absorbed.channels().into_iter().zip(color.channels()) {
+            assert_approx_eq::assert_approx_eq!(a, c.powf(exponent));
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "transmittance distance must be positive")]
+fn test_dielectric_transmittance_rejects_zero_distance() {
+    let _ = Dielectric::with_transmittance(1.5, ARgb::new(1.0, 0.5, 0.5), 0.0);
+}
+
+#[test]
+fn test_rough_dielectric_reflects_totally_beyond_critical_angle() {
+    use super::hittable::NormalFace;
+
+    let outward = Point::new(0.0, 0.0, 1.0);
+    // leaving glass of ior 1.5, the critical angle is about 41.8 degrees. A smooth
+    // surface reflects everything, the tail of a rough one tilts a few microfacets
+    // below the critical angle and lets a little light out
+    for (roughness, min_reflected) in [(0.0, 1.0), (0.1, 0.8)] {
+        let glass: Arc<dyn Material> = Arc::new(RoughDielectric::new(1.5, roughness));
+        for degrees in [60.0_f64, 75.0, 85.0] {
+            let theta = degrees.to_radians();
+            let dir = Point::new(theta.sin(), 0.0, theta.cos());
+            let r_in = Ray::new(Point::new(0.0, 0.0, -1.0) - dir, dir, None);
+            let mut hr = HitRec::new(Point::new(0.0, 0.0, -1.0), outward, 1.0, Arc::clone(&glass));
+            hr.set_face_normal(&r_in, &outward);
+            assert!(matches!(hr.face, NormalFace::Inside));
+
+            let count = 2_000;
+            let (mut kept, mut reflected) = (0.0, 0);
+            for _ in 0..count {
+                let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+                if !glass.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+                    continue;
+                }
+                if scattered.dir().scalar_prod(&outward) < 0.0 {
+                    reflected += 1;
+                }
+                for c in attenuation.channels() {
+                    assert!((0.0..=1.0 + 1e-9).contains(&c));
+                }
+                kept += attenuation.luminance();
+            }
+            let reflected = f64::from(reflected) / f64::from(count);
+            assert!(
+                reflected >= min_reflected,
+                "{degrees} {roughness} {reflected}"
+            );
+            // no energy is created, and little is lost to masking at low roughness
+            let mean = kept / f64::from(count);
+            assert!(
+                mean <= 1.0 + 1e-9 && mean > 0.85,
+                "{degrees} {roughness} {mean}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_rough_dielectric_conserves_energy_from_outside() {
+    let outward = Point::new(0.0, 0.0, 1.0);
+    for roughness in [0.0, 0.3, 0.8] {
+        let glass: Arc<dyn Material> = Arc::new(RoughDielectric::new(1.5, roughness));
+        for degrees in [0.0_f64, 45.0, 80.0] {
+            let theta = degrees.to_radians();
+            let dir = Point::new(theta.sin(), 0.0, -theta.cos());
+            let r_in = Ray::new(-dir, dir, None);
+            let mut hr = HitRec::new(Point::default(), outward, 1.0, Arc::clone(&glass));
+            hr.set_face_normal(&r_in, &outward);
+
+            let count = 4_000;
+            let (mut total, mut reflected) = (0.0, 0.0);
+            for _ in 0..count {
+                let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+                if glass.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+                    total += attenuation.luminance();
+                    if scattered.dir().scalar_prod(&outward) > 0.0 {
+                        reflected += attenuation.luminance();
+                    }
+                }
+            }
+            let (total, reflected) = (total / f64::from(count), reflected / f64::from(count));
+            assert!(
+                total <= 1.0 + 1e-9 && total > 0.75,
+                "{degrees} {roughness} {total}"
+            );
+            // glass mostly transmits, fresnel reflection grows towards grazing angles
+            let fresnel = microfacet::fresnel_dielectric(theta.cos(), 1.5);
+            if roughness == 0.0 {
+                assert!(
+                    (reflected - fresnel).abs() < 0.03,
+                    "{degrees} {reflected} {fresnel}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_diffuse_and_metal_bounces_stay_inside_the_medium() {
+    use super::{hittable::Hittable, sphere::Sphere};
+    use crate::utils::interval::Interval;
+
+    // pebble lying in a ball of murky water
+    let water: Arc<dyn Material> =
+        Arc::new(Dielectric::with_absorption(1.33, ARgb::new(0.5, 0.1, 0.1)));
+    let water_ball = Sphere::new_static(2.0, Point::default(), water);
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let ray = Ray::new(Point::new(0.0, 0.0, -5.0), Point::new(0.0, 0.0, 1.0), None);
+    let entry = water_ball.hit(&ray, &ray_t).expect("ray reaches the water");
+    let mut attenuation = ARgb::default();
+    let in_water = loop {
+        let mut scattered = Ray::default();
+        assert!(entry
+            .mat
+            .scatter(&ray, &mut attenuation, &mut scattered, &entry));
+        // fresnel reflects a few percent, wait for a refracted one
+        if scattered.dir().z() > 0.0 {
+            break scattered;
+        }
+    };
+    assert_eq!(in_water.media().current().map(|m| m.ior), Some(1.33));
+
+    let pebble_materials: [Arc<dyn Material>; 2] = [
+        Arc::new(Lambertian::new(ARgb::new(0.5, 0.5, 0.5), 1.0)),
+        Arc::new(Metal::new(ARgb::new(0.8, 0.8, 0.8), Some(0.3))),
+    ];
+    for material in pebble_materials {
+        let pebble = Sphere::new_static(0.5, Point::default(), material);
+        let hr = pebble
+            .hit(&in_water, &ray_t)
+            .expect("ray reaches the pebble");
+        let mut bounced = Ray::default();
+        if !hr
+            .mat
+            .scatter(&in_water, &mut attenuation, &mut bounced, &hr)
+        {
+            continue;
+        }
+        // still in the water, so leaving it is refraction out of 1.33 with absorption
+        assert_eq!(bounced.media().current().map(|m| m.ior), Some(1.33));
+        let exit = water_ball
+            .hit(&bounced, &ray_t)
+            .expect("ray leaves the water");
+        let water_entry = *bounced.media().current().expect("inside the water");
+        let crossing = Crossing::resolve(water_entry, &bounced, &exit);
+        assert_eq!(crossing.etas.map(|(eta_i, _)| eta_i), Some(1.33));
+        assert!(bounced.transmittance(exit.t).channels()[0] < 1.0);
+    }
+}
+
+#[cfg(test)]
+fn flat_hit(theta_degrees: f64, phi_degrees: f64) -> (Ray, HitRec) {
+    // ray hitting the xy plane at the origin from direction (theta, phi) above it, with u
+    // along x so anisotropic materials are oriented
+    let n = Point::new(0.0, 0.0, 1.0);
+    let (theta, phi) = (theta_degrees.to_radians(), phi_degrees.to_radians());
+    let to_eye = Point::new(
+        theta.sin() * phi.cos(),
+        theta.sin() * phi.sin(),
+        theta.cos(),
+    );
+    let r_in = Ray::new(to_eye, -to_eye, None);
+    let mut hr = HitRec::new(
+        Point::default(),
+        n,
+        1.0,
+        Arc::new(Lambertian::new(ARgb::default(), 1.0)),
+    );
+    hr.set_face_normal(&r_in, &n);
+    hr.set_tangents(Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0));
+    (r_in, hr)
+}
+
+#[test]
+fn test_rough_anisotropic_conductor_passes_white_furnace() {
+    // huge extinction reflects everything, whatever is missing is lost by the microfacets
+    let white = Conductor::new(ARgb::new(1.0, 1.0, 1.0), ARgb::new(1e4, 1e4, 1e4), 0.4, 0.8);
+    let n = 40_000;
+    for (theta, phi) in [(0.0, 0.0), (45.0, 0.0), (45.0, 90.0), (75.0, 30.0)] {
+        let (r_in, hr) = flat_hit(theta, phi);
+        let mut total = 0.0;
+        for _ in 0..n {
+            let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+            if white.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+                total += attenuation.luminance();
+            }
+        }
+        let albedo = total / f64::from(n);
+        assert!(
+            albedo <= 1.0 + 1e-9 && albedo > 0.85,
+            "{theta} {phi}: {albedo}"
+        );
+    }
+}
+
+#[test]
+fn test_rough_conductor_sampling_agrees_with_eval() {
+    // scatter estimates the integral of eval by importance sampling, light sampling uses
+    // eval directly; both have to see the same lobe, including where it leans to
+    let gold = Conductor::from_preset(ConductorPreset::Gold, 0.5, 0.6);
+    let regions: [fn(&Point) -> f64; 3] = [
+        |_| 1.0,
+        |wi| f64::from(u8::from(wi.x() > 0.3)),
+        |wi| f64::from(u8::from(wi.y() > 0.3)),
+    ];
+    let mut rng = rand::rng();
+    let n = 100_000;
+    for (theta, phi) in [(30.0, 0.0), (60.0, 60.0)] {
+        let (r_in, hr) = flat_hit(theta, phi);
+        for region in regions {
+            let (mut sampled, mut integrated) = (ARgb::default(), ARgb::default());
+            for _ in 0..n {
+                let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+                if gold.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+                    sampled = sampled + attenuation * region(&scattered.dir().unit());
+                }
+                // uniform over the hemisphere, pdf 1 / 2pi
+                let wi = Point::random_unit_on_sphere(&mut rng);
+                let wi = Point::new(wi.x(), wi.y(), wi.z().abs());
+                let f = gold
+                    .eval(&r_in, &wi, &hr)
+                    .expect("rough conductor is evaluable");
+                integrated = integrated + f * (region(&wi) * std::f64::consts::TAU);
+            }
+            let (sampled, integrated) = (sampled / f64::from(n), integrated / f64::from(n));
+            for (s, i) in sampled.channels().iter().zip(integrated.channels()) {
+                assert!(
+                    (s - i).abs() < 0.02,
+                    "{theta} {phi}: {sampled:?} vs {integrated:?}"
+                );
+            }
+        }
+    }
+}
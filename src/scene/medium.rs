@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use rand::{Rng, RngCore};
+
+use crate::core::{ray::Ray, rgb::ARgb};
+
+use super::{
+    hittable::HitRec,
+    material::{Material, Matte},
+    phase::{IsotropicPhase, PhaseFunction, PhaseMaterial},
+    texture::{SolidColor, Texture},
+};
+
+// what happens to a ray travelling through a homogeneous medium
+pub enum Interaction {
+    // event at distance from the ray origin (in world units), material scatters the ray
+    // or, for null collisions, lets it go on
+    Scatter {
+        distance: f64,
+        material: Arc<dyn Material>,
+    },
+    // the ray leaves the medium, throughput is multiplied by tint if there is one
+    Pass {
+        tint: Option<ARgb>,
+    },
+}
+
+// Homogeneous participating medium with per channel absorption and scattering coefficients.
+// Distances are sampled with one randomly picked channel and weighted by the average
+// pdf over all channels (spectral MIS), which keeps weights bounded for coloured media.
+pub struct Medium {
+    sigma_t: ARgb,
+    albedo: Arc<dyn Texture>,
+    phase: Arc<dyn PhaseFunction>,
+    grey: bool,
+    // shared material for media where no per event weight is needed
+    unweighted: Arc<dyn Material>,
+}
+
+impl Medium {
+    pub fn new(sigma_a: ARgb, sigma_s: ARgb, phase: Arc<dyn PhaseFunction>) -> Self {
+        let [sa, ss] = [sigma_a.channels(), sigma_s.channels()];
+        let sigma_t = ARgb::new(sa[0] + ss[0], sa[1] + ss[1], sa[2] + ss[2]);
+        let t = sigma_t.channels();
+        let ratio = |s: f64, t: f64| if t > 0.0 { s / t } else { 0.0 };
+        let albedo = ARgb::new(ratio(ss[0], t[0]), ratio(ss[1], t[1]), ratio(ss[2], t[2]));
+        let albedo: Arc<dyn Texture> = Arc::new(SolidColor::new(albedo));
+        Self::build(sigma_t, albedo, phase)
+    }
+
+    // classic fog: single density for all channels, colour comes from albedo
+    pub fn grey(density: f64, albedo: &Arc<dyn Texture>, phase: Arc<dyn PhaseFunction>) -> Self {
+        Self::build(
+            ARgb::new(density, density, density),
+            Arc::clone(albedo),
+            phase,
+        )
+    }
+
+    #[must_use]
+    pub fn isotropic(density: f64, albedo: ARgb) -> Self {
+        let albedo: Arc<dyn Texture> = Arc::new(SolidColor::new(albedo));
+        Self::grey(density, &albedo, Arc::new(IsotropicPhase))
+    }
+
+    // exact comparison is intended, grey media are built from a single density
+    #[allow(clippy::float_cmp)]
+    fn build(sigma_t: ARgb, albedo: Arc<dyn Texture>, phase: Arc<dyn PhaseFunction>) -> Self {
+        let [r, g, b] = sigma_t.channels();
+        Self {
+            sigma_t,
+            grey: r == g && g == b,
+            unweighted: Arc::new(PhaseMaterial::new(&albedo, Arc::clone(&phase))),
+            albedo,
+            phase,
+        }
+    }
+
+    pub fn sample(&self, max_distance: f64, rng: &mut dyn RngCore) -> Interaction {
+        let sigma_t = self.sigma_t.channels();
+        let sigma = sigma_t[rng.random_range(0..3)];
+        let distance = if sigma > 0.0 {
+            -f64::ln(1.0 - rng.random::<f64>()) / sigma
+        } else {
+            f64::INFINITY
+        };
+
+        if distance < max_distance {
+            let material = if self.grey {
+                Arc::clone(&self.unweighted)
+            } else {
+                let f = self.sigma_t.map(|s| s * f64::exp(-s * distance));
+                let [fr, fg, fb] = f.channels();
+                let pdf = (fr + fg + fb) / 3.0;
+                Arc::new(PhaseMaterial::weighted(
+                    &self.albedo,
+                    Arc::clone(&self.phase),
+                    f / pdf,
+                )) as Arc<dyn Material>
+            };
+            Interaction::Scatter { distance, material }
+        } else {
+            let tint = (!self.grey).then(|| {
+                let tr = self.transmittance(max_distance);
+                let [r, g, b] = tr.channels();
+                tr / ((r + g + b) / 3.0)
+            });
+            Interaction::Pass { tint }
+        }
+    }
+
+    // Spectral tracking: tentative collisions at the rate of the densest channel, each one
+    // either scatters or is null and passes the ray on reweighted. Unlike sample, rays
+    // which get through need no weight, so they can end on anything inside of the medium.
+    pub fn track(&self, max_distance: f64, rng: &mut dyn RngCore) -> Interaction {
+        if self.grey {
+            return self.sample(max_distance, rng);
+        }
+        let majorant = self.sigma_t.channels().into_iter().fold(0.0, f64::max);
+        let distance = -f64::ln(1.0 - rng.random::<f64>()) / majorant;
+        if distance >= max_distance {
+            return Interaction::Pass { tint: None };
+        }
+
+        let mean = |c: ARgb| {
+            let [r, g, b] = c.channels();
+            (r + g + b) / 3.0
+        };
+        let sigma_n = self.sigma_t.map(|s| majorant - s);
+        let material: Arc<dyn Material> = if rng.random::<f64>() * majorant < mean(self.sigma_t) {
+            Arc::new(PhaseMaterial::weighted(
+                &self.albedo,
+                Arc::clone(&self.phase),
+                self.sigma_t / mean(self.sigma_t),
+            ))
+        } else {
+            Arc::new(Passthrough::new(sigma_n / mean(sigma_n)))
+        };
+        Interaction::Scatter { distance, material }
+    }
+
+    #[must_use]
+    pub fn transmittance(&self, distance: f64) -> ARgb {
+        self.sigma_t.map(|s| f64::exp(-s * distance))
+    }
+
+    // probability of passing the distance without scattering, used for shadow rays
+    #[must_use]
+    pub fn mean_transmittance(&self, distance: f64) -> f64 {
+        let [r, g, b] = self.transmittance(distance).channels();
+        (r + g + b) / 3.0
+    }
+}
+
+// continues the ray unchanged, tinted by weight; used for null collisions and where
+// a coloured medium ends
+pub struct Passthrough {
+    weight: ARgb,
+}
+
+impl Passthrough {
+    #[must_use]
+    pub fn new(weight: ARgb) -> Self {
+        Self { weight }
+    }
+}
+
+impl Material for Passthrough {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        *scattered = r_in.continued(hr.p, r_in.dir());
+        *attenuation = self.weight;
+        true
+    }
+
+    fn matte(&self) -> Option<Matte> {
+        Some(Matte::Transparent)
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::Arc;
+
+use crate::{
+    core::{point3::Point, ray::Ray},
+    utils::interval::Interval,
+};
+
+use super::{
+    aabb::Aabb,
+    bvh::Bvh,
+    hittable::{HitRec, Hittable, Opacity},
+    material::Material,
+    quad::FLAT_PAD,
+};
+
+#[derive(Debug)]
+pub enum MeshError {
+    NoFaces,
+    // index of the face and the vertex index it refers to
+    VertexOutOfRange(usize, usize),
+    // index of the face and its material id
+    MaterialOutOfRange(usize, usize),
+    UvCountMismatch { expected: usize, found: usize },
+}
+
+// vertex indices counter-clockwise seen from the front, material is an index into the
+// materials of the mesh
+#[derive(Clone, Copy, Debug)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub material: usize,
+}
+
+// Triangle, front (outside) is where the vertices go counter-clockwise.
+pub struct Triangle {
+    vertices: [Point; 3],
+    uvs: [(f64, f64); 3],
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+    opacity: Option<Opacity>,
+}
+
+impl Triangle {
+    // texture coordinates default to (0, 0), (1, 0) and (0, 1)
+    pub fn new(vertices: [Point; 3], mat: Arc<dyn Material>) -> Self {
+        Self::with_uvs(vertices, [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], mat)
+    }
+
+    pub fn with_uvs(vertices: [Point; 3], uvs: [(f64, f64); 3], mat: Arc<dyn Material>) -> Self {
+        let [a, b, c] = vertices;
+        let bbox = Aabb::from_points(&a, &b)
+            .expand(&Aabb::from_points(&c, &c))
+            .padded(FLAT_PAD);
+        Self {
+            vertices,
+            uvs,
+            mat,
+            bbox,
+            opacity: None,
+        }
+    }
+
+    // alpha cutout, looked up with the interpolated texture coordinates
+    #[must_use]
+    pub fn with_opacity(mut self, opacity: Opacity) -> Self {
+        self.opacity = Some(opacity);
+        self
+    }
+
+    // t and barycentric coordinates of b and c (moller-trumbore), cut out parts are missed
+    fn intersect(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<(f64, f64, f64)> {
+        let [a, b, c] = self.vertices;
+        let (e1, e2) = (b - a, c - a);
+        let pvec = ray.dir().cross(&e2);
+        let det = e1.scalar_prod(&pvec);
+        // parallel to the plane or degenerate
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+        let tvec = ray.orig() - a;
+        let beta = tvec.scalar_prod(&pvec) * inv_det;
+        if !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        let qvec = tvec.cross(&e1);
+        let gamma = ray.dir().scalar_prod(&qvec) * inv_det;
+        if gamma < 0.0 || beta + gamma > 1.0 {
+            return None;
+        }
+        let t = e2.scalar_prod(&qvec) * inv_det;
+        if !ray_t_possible.surrounds(t) {
+            return None;
+        }
+        let kept = self.opacity.as_ref().is_none_or(|opacity| {
+            let (u, v) = self.uv(beta, gamma);
+            opacity.keeps(u, v, &ray.at(t))
+        });
+        kept.then_some((t, beta, gamma))
+    }
+
+    // texture coordinates at barycentric coordinates of b and c
+    fn uv(&self, beta: f64, gamma: f64) -> (f64, f64) {
+        let alpha = 1.0 - beta - gamma;
+        let [uv_a, uv_b, uv_c] = self.uvs;
+        (
+            alpha * uv_a.0 + beta * uv_b.0 + gamma * uv_c.0,
+            alpha * uv_a.1 + beta * uv_b.1 + gamma * uv_c.1,
+        )
+    }
+
+    // dp/du and dp/dv from the texture coordinates of the corners,
+    // zero when they don't span the triangle
+    fn tangents(&self) -> (Point, Point) {
+        let [a, b, c] = self.vertices;
+        let [uv_a, uv_b, uv_c] = self.uvs;
+        let (du1, dv1) = (uv_b.0 - uv_a.0, uv_b.1 - uv_a.1);
+        let (du2, dv2) = (uv_c.0 - uv_a.0, uv_c.1 - uv_a.1);
+        let det = du1 * dv2 - dv1 * du2;
+        if det.abs() < 1e-12 {
+            return (Point::default(), Point::default());
+        }
+        let (e1, e2) = (b - a, c - a);
+        ((e1 * dv2 - e2 * dv1) / det, (e2 * du1 - e1 * du2) / det)
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        let (t, beta, gamma) = self.intersect(ray, ray_t_possible)?;
+        let [a, b, c] = self.vertices;
+        let outward_normal = (b - a).cross(&(c - a)).unit();
+
+        let mut hr = HitRec::new(ray.at(t), outward_normal, t, Arc::clone(&self.mat));
+        hr.set_face_normal(ray, &outward_normal);
+        hr.set_uv(self.uv(beta, gamma));
+        let (dpdu, dpdv) = self.tangents();
+        hr.set_tangents(dpdu, dpdv);
+        Some(hr)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.intersect(ray, ray_t_possible).is_some()
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
+}
+
+// Indexed triangle mesh, every face picks its material by id, so one mesh can hold
+// e.g. the cover and the pages of a book. Faces are kept in their own bvh.
+pub struct Mesh {
+    bvh: Bvh,
+}
+
+impl Mesh {
+    // uvs, when given, are per vertex
+    #[allow(clippy::missing_errors_doc)]
+    pub fn new(
+        positions: &[Point],
+        uvs: Option<&[(f64, f64)]>,
+        faces: &[MeshFace],
+        materials: &[Arc<dyn Material>],
+    ) -> Result<Self, MeshError> {
+        if faces.is_empty() {
+            return Err(MeshError::NoFaces);
+        }
+        if let Some(uvs) = uvs {
+            if uvs.len() != positions.len() {
+                return Err(MeshError::UvCountMismatch {
+                    expected: positions.len(),
+                    found: uvs.len(),
+                });
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(faces.len());
+        for (i, face) in faces.iter().enumerate() {
+            if let Some(&index) = face.vertices.iter().find(|&&v| v >= positions.len()) {
+                return Err(MeshError::VertexOutOfRange(i, index));
+            }
+            let mat = materials
+                .get(face.material)
+                .ok_or(MeshError::MaterialOutOfRange(i, face.material))?;
+            let vertices = face.vertices.map(|v| positions[v]);
+            let triangle = match uvs {
+                Some(uvs) => {
+                    Triangle::with_uvs(vertices, face.vertices.map(|v| uvs[v]), Arc::clone(mat))
+                }
+                None => Triangle::new(vertices, Arc::clone(mat)),
+            };
+            triangles.push(Arc::new(triangle) as Arc<dyn Hittable>);
+        }
+        Ok(Self {
+            bvh: Bvh::new(&mut triangles),
+        })
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        self.bvh.hit(ray, ray_t_possible)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.bvh.occluded(ray, ray_t_possible)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+#[test]
+fn test_mesh_faces_use_their_material_id() {
+    use super::material::Lambertian;
+    use crate::core::rgb::ARgb;
+
+    let red: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 0.0, 0.0), 1.0));
+    let blue: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.0, 0.0, 1.0), 1.0));
+    let materials = [red, blue];
+    // unit square in the xy plane split along its diagonal, facing +z
+    let positions = [
+        Point::new(0.0, 0.0, 0.0),
+        Point::new(1.0, 0.0, 0.0),
+        Point::new(1.0, 1.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+    ];
+    let faces = [
+        MeshFace {
+            vertices: [0, 1, 2],
+            material: 0,
+        },
+        MeshFace {
+            vertices: [0, 2, 3],
+            material: 1,
+        },
+    ];
+    let mesh = Mesh::new(&positions, None, &faces, &materials).expect("valid mesh");
+
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    for (x, y, expected) in [(0.8, 0.2, &materials[0]), (0.2, 0.8, &materials[1])] {
+        let ray = Ray::new(Point::new(x, y, 1.0), Point::new(0.0, 0.0, -1.0), None);
+        let hr = mesh.hit(&ray, &ray_t).expect("ray hits the square");
+        assert_approx_eq::assert_approx_eq!(hr.t, 1.0);
+        assert!(matches!(hr.face, super::hittable::NormalFace::Outside));
+        assert!(Arc::ptr_eq(&hr.mat, expected));
+    }
+
+    let bad = [MeshFace {
+        vertices: [0, 1, 2],
+        material: 2,
+    }];
+    assert!(matches!(
+        Mesh::new(&positions, None, &bad, &materials),
+        Err(MeshError::MaterialOutOfRange(0, 2))
+    ));
+}
@@ -0,0 +1,484 @@
+use std::{
+    f64::consts::{PI, TAU},
+    ops::{Add, Div, Mul, Sub},
+    sync::LazyLock,
+};
+
+use rand::{Rng, RngCore};
+
+use crate::core::{point3::Point, rgb::ARgb};
+
+// All directions here are in local shading space: normal is z, both directions
+// point away from the surface.
+
+// below this alpha the surface is treated as perfectly smooth
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals,
+// alpha_x != alpha_y gives anisotropic highlights stretched along the tangent
+#[derive(Clone, Copy, Debug)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    #[must_use]
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    // perceptual roughness in [0, 1] is squared for alpha, anisotropy in [0, 1)
+    // stretches the lobe along the tangent (disney parametrization)
+    #[must_use]
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness.clamp(0.0, 1.0).powi(2);
+        let aspect = f64::sqrt(1.0 - 0.9 * anisotropy.clamp(0.0, 1.0));
+        Self::new(
+            f64::max(alpha / aspect, 1e-4),
+            f64::max(alpha * aspect, 1e-4),
+        )
+    }
+
+    #[must_use]
+    pub fn effectively_smooth(&self) -> bool {
+        f64::max(self.alpha_x, self.alpha_y) < SMOOTH_ALPHA
+    }
+
+    #[must_use]
+    pub fn d(&self, wm: &Point) -> f64 {
+        let cos2 = wm.z() * wm.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let x = wm.x() / self.alpha_x;
+        let y = wm.y() / self.alpha_y;
+        let e = x * x + y * y + cos2;
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    fn lambda(&self, w: &Point) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let ax = w.x() * self.alpha_x;
+        let ay = w.y() * self.alpha_y;
+        let tan2_alpha2 = (ax * ax + ay * ay) / cos2;
+        (f64::sqrt(1.0 + tan2_alpha2) - 1.0) / 2.0
+    }
+
+    #[must_use]
+    pub fn g1(&self, w: &Point) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // height-correlated masking-shadowing
+    #[must_use]
+    pub fn g(&self, wo: &Point, wi: &Point) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // distribution of normals visible from w
+    #[must_use]
+    pub fn pdf(&self, w: &Point, wm: &Point) -> f64 {
+        if w.z() == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z().abs() * self.d(wm) * w.scalar_prod(wm).abs()
+    }
+
+    // visible normal sampling (heitz 2018): only normals facing w are generated,
+    // so the weight of reflection is G(wo, wi) / G1(wo) and never explodes
+    pub fn sample_wm(&self, w: &Point, rng: &mut dyn RngCore) -> Point {
+        let flip = w.z() < 0.0;
+        let w = if flip { -*w } else { *w };
+        // to the hemisphere configuration
+        let wh = Point::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()).unit();
+        let t1 = if wh.z() < 0.99999 {
+            Point::new(0.0, 0.0, 1.0).cross(&wh).unit()
+        } else {
+            Point::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // uniform point on the projected disk, warped to the visible half
+        let r = rng.random::<f64>().sqrt();
+        let phi = TAU * rng.random::<f64>();
+        let (px, py) = (r * phi.cos(), r * phi.sin());
+        let h = f64::sqrt(1.0 - px * px);
+        let s = 0.5 * (1.0 + wh.z());
+        let py = (1.0 - s) * h + s * py;
+        let pz = f64::sqrt(f64::max(0.0, 1.0 - px * px - py * py));
+        let nh = t1 * px + t2 * py + wh * pz;
+
+        // back to the ellipsoid configuration
+        let wm = Point::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            f64::max(1e-6, nh.z()),
+        )
+        .unit();
+        if flip {
+            -wm
+        } else {
+            wm
+        }
+    }
+}
+
+// "Charlie" sheen distribution (estevez & kulla 2017): fibers standing up from cloth,
+// most microfacet normals lie close to the surface, which gives grazing highlights
+#[derive(Clone, Copy, Debug)]
+pub struct Charlie {
+    alpha: f64,
+}
+
+impl Charlie {
+    // perceptual roughness squared as with ggx
+    #[must_use]
+    pub fn from_roughness(roughness: f64) -> Self {
+        Self {
+            alpha: roughness.clamp(CHARLIE_MIN_ROUGHNESS, 1.0).powi(2),
+        }
+    }
+
+    #[must_use]
+    pub fn d(self, wm: &Point) -> f64 {
+        let sin2 = f64::max(0.0, 1.0 - wm.z() * wm.z());
+        let inv_alpha = 1.0 / self.alpha;
+        (2.0 + inv_alpha) * sin2.powf(0.5 * inv_alpha) / TAU
+    }
+
+    // ashikhmin's smooth visibility term instead of the fitted masking of the paper,
+    // already includes the 1 / (4 cos_o cos_i) of microfacet brdfs
+    #[must_use]
+    pub fn visibility(wo: &Point, wi: &Point) -> f64 {
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        1.0 / (4.0 * (cos_i + cos_o - cos_i * cos_o))
+    }
+
+    // brdf times cos for both directions in the upper hemisphere, without the colour.
+    // The smooth visibility reflects more than comes in towards grazing angles, so the
+    // lobe is scaled down wherever its albedo would exceed one
+    #[must_use]
+    pub fn eval(self, wo: &Point, wi: &Point) -> f64 {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        self.eval_raw(wo, wi) / self.albedo(wo.z()).max(1.0)
+    }
+
+    fn eval_raw(self, wo: &Point, wi: &Point) -> f64 {
+        let wm = (*wo + *wi).unit();
+        self.d(&wm) * Self::visibility(wo, wi) * wi.z()
+    }
+
+    // directional albedo of the unscaled lobe, the largest tabulated value around
+    // (roughness, cos_o) so the scaled lobe stays below one in between the samples too;
+    // the albedo grows towards grazing and the lower cos bound is the larger one
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn albedo(self, cos_o: f64) -> f64 {
+        let r = (self.alpha.sqrt() - CHARLIE_MIN_ROUGHNESS) / (1.0 - CHARLIE_MIN_ROUGHNESS)
+            * (CHARLIE_ROUGHNESS_SAMPLES - 1) as f64;
+        let c = cos_o.clamp(0.0, 1.0) * (CHARLIE_COS_SAMPLES - 1) as f64;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let (r, c) = (r.max(0.0) as usize, c as usize);
+        let r_next = (r + 1).min(CHARLIE_ROUGHNESS_SAMPLES - 1);
+        let table = &*CHARLIE_ALBEDO;
+        f64::max(table[r][c], table[r_next][c])
+    }
+
+    // midpoint rule over cos_i and the half of the azimuths on one side of wo
+    #[allow(clippy::cast_precision_loss)]
+    fn integrate_albedo(self, cos_o: f64) -> f64 {
+        const STEPS: usize = 64;
+        let wo = Point::new(f64::sqrt(1.0 - cos_o * cos_o), 0.0, cos_o);
+        let mut total = 0.0;
+        for i in 0..STEPS {
+            let cos_i = (i as f64 + 0.5) / STEPS as f64;
+            let sin_i = f64::sqrt(1.0 - cos_i * cos_i);
+            for j in 0..STEPS / 2 {
+                let phi = PI * (j as f64 + 0.5) / (STEPS / 2) as f64;
+                let wi = Point::new(sin_i * phi.cos(), sin_i * phi.sin(), cos_i);
+                total += self.eval_raw(&wo, &wi);
+            }
+        }
+        total * TAU / (STEPS * STEPS / 2) as f64
+    }
+}
+
+// very low roughness is a needle-sharp rim nobody asks for
+const CHARLIE_MIN_ROUGHNESS: f64 = 0.07;
+const CHARLIE_ROUGHNESS_SAMPLES: usize = 16;
+const CHARLIE_COS_SAMPLES: usize = 32;
+
+#[allow(clippy::cast_precision_loss)]
+static CHARLIE_ALBEDO: LazyLock<Vec<[f64; CHARLIE_COS_SAMPLES]>> = LazyLock::new(|| {
+    (0..CHARLIE_ROUGHNESS_SAMPLES)
+        .map(|r| {
+            let roughness = CHARLIE_MIN_ROUGHNESS
+                + (1.0 - CHARLIE_MIN_ROUGHNESS) * r as f64 / (CHARLIE_ROUGHNESS_SAMPLES - 1) as f64;
+            let charlie = Charlie::from_roughness(roughness);
+            std::array::from_fn(|c| {
+                charlie.integrate_albedo(c as f64 / (CHARLIE_COS_SAMPLES - 1) as f64)
+            })
+        })
+        .collect()
+});
+
+#[must_use]
+pub fn reflect(wo: &Point, n: &Point) -> Point {
+    -*wo + *n * (2.0 * wo.scalar_prod(n))
+}
+
+// wi refracted through microfacet with normal wm, eta is eta_t / eta_i.
+// None on total internal reflection
+#[must_use]
+pub fn refract(wo: &Point, wm: &Point, eta: f64) -> Option<Point> {
+    let cos_i = wo.scalar_prod(wm);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    Some(-*wo / eta + *wm * (cos_i / eta - cos_t))
+}
+
+// exact unpolarized fresnel reflectance of a dielectric interface, eta is eta_t / eta_i
+#[must_use]
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    f64::midpoint(r_parl * r_parl, r_perp * r_perp)
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn norm(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // e^(i * self)
+    fn exp_i(self) -> Self {
+        let scale = f64::exp(-self.im);
+        Complex::new(scale * self.re.cos(), scale * self.re.sin())
+    }
+
+    fn sqrt(self) -> Self {
+        let n = self.norm().sqrt();
+        if n == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        let t1 = f64::sqrt(0.5 * (n + self.re.abs()));
+        let t2 = 0.5 * self.im / t1;
+        if self.re >= 0.0 {
+            Complex::new(t1, t2)
+        } else {
+            Complex::new(t2.abs(), t1.copysign(self.im))
+        }
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Complex::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let scale = 1.0 / rhs.norm();
+        Complex::new(
+            scale * (self.re * rhs.re + self.im * rhs.im),
+            scale * (self.im * rhs.re - self.re * rhs.im),
+        )
+    }
+}
+
+// exact fresnel reflectance of a conductor with complex ior eta + i*k
+fn fresnel_complex(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i = Complex::new(cos_i.clamp(0.0, 1.0), 0.0);
+    let eta = Complex::new(eta, k);
+    let one = Complex::new(1.0, 0.0);
+    let sin2_i = one - cos_i * cos_i;
+    let sin2_t = sin2_i / (eta * eta);
+    let cos_t = (one - sin2_t).sqrt();
+
+    let r_parl = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perp = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    f64::midpoint(r_parl.norm(), r_perp.norm())
+}
+
+#[must_use]
+pub fn fresnel_conductor(cos_i: f64, eta: &ARgb, k: &ARgb) -> ARgb {
+    let [er, eg, eb] = eta.channels();
+    let [kr, kg, kb] = k.channels();
+    ARgb::new(
+        fresnel_complex(cos_i, er, kr),
+        fresnel_complex(cos_i, eg, kg),
+        fresnel_complex(cos_i, eb, kb),
+    )
+}
+
+// wavelengths in nm standing for the red, green and blue channels
+const RGB_WAVELENGTHS: [f64; 3] = [650.0, 550.0, 450.0];
+
+// reflectance of a single wavelength with thin film of ior n2 between media n1 and n3
+// (airy summation of all reflections inside of the film), averaged over polarizations
+fn airy(cos_1: f64, n1: f64, n2: f64, n3: Complex, thickness: f64, wavelength: f64) -> f64 {
+    let one = Complex::from(1.0);
+    let cos_1 = Complex::from(cos_1.clamp(0.0, 1.0));
+    let sin2_1 = one - cos_1 * cos_1;
+    let (n1, n2) = (Complex::from(n1), Complex::from(n2));
+    // complex cosines cover total internal reflection inside of the film
+    let cos_2 = (one - sin2_1 * (n1 * n1) / (n2 * n2)).sqrt();
+    let cos_3 = (one - sin2_1 * (n1 * n1) / (n3 * n3)).sqrt();
+
+    let r12_s = (n1 * cos_1 - n2 * cos_2) / (n1 * cos_1 + n2 * cos_2);
+    let r12_p = (n2 * cos_1 - n1 * cos_2) / (n2 * cos_1 + n1 * cos_2);
+    let r23_s = (n2 * cos_2 - n3 * cos_3) / (n2 * cos_2 + n3 * cos_3);
+    let r23_p = (n3 * cos_2 - n2 * cos_3) / (n3 * cos_2 + n2 * cos_3);
+
+    // phase difference of one round trip through the film
+    let delta = Complex::from(4.0 * PI * thickness / wavelength) * n2 * cos_2;
+    let shift = delta.exp_i();
+    let total = |r12: Complex, r23: Complex| (r12 + r23 * shift) / (one + r12 * r23 * shift);
+    f64::midpoint(total(r12_s, r23_s).norm(), total(r12_p, r23_p).norm())
+}
+
+// Reflectance of a surface covered by a thin film (soap, oil, anodized oxide) of given
+// ior and thickness in nm. Light comes from a medium of ior eta_i, the substrate has
+// complex ior eta + i*k per channel (k is zero for dielectrics).
+#[must_use]
+pub fn fresnel_thin_film(
+    cos_i: f64,
+    eta_i: f64,
+    film_ior: f64,
+    thickness: f64,
+    eta: &ARgb,
+    k: &ARgb,
+) -> ARgb {
+    let [er, eg, eb] = eta.channels();
+    let [kr, kg, kb] = k.channels();
+    let [lr, lg, lb] = RGB_WAVELENGTHS;
+    ARgb::new(
+        airy(cos_i, eta_i, film_ior, Complex::new(er, kr), thickness, lr),
+        airy(cos_i, eta_i, film_ior, Complex::new(eg, kg), thickness, lg),
+        airy(cos_i, eta_i, film_ior, Complex::new(eb, kb), thickness, lb),
+    )
+}
+
+#[test]
+fn test_fresnel_complex_matches_dielectric_at_normal_incidence() {
+    // without extinction it is ((eta - 1) / (eta + 1))^2
+    assert_approx_eq::assert_approx_eq!(fresnel_complex(1.0, 1.5, 0.0), 0.04);
+    // conductors reflect everything at grazing angles
+    assert_approx_eq::assert_approx_eq!(fresnel_complex(0.0, 0.2, 3.9), 1.0);
+}
+
+#[test]
+fn test_fresnel_dielectric_matches_complex_and_reflects_totally() {
+    assert_approx_eq::assert_approx_eq!(
+        fresnel_dielectric(0.7, 1.5),
+        fresnel_complex(0.7, 1.5, 0.0)
+    );
+    // total internal reflection leaving glass at grazing angle
+    assert_approx_eq::assert_approx_eq!(fresnel_dielectric(0.2, 1.0 / 1.5), 1.0);
+    assert!(refract(
+        &Point::new(0.98, 0.0, 0.2).unit(),
+        &Point::new(0.0, 0.0, 1.0),
+        1.0 / 1.5
+    )
+    .is_none());
+}
+
+#[test]
+fn test_thin_film_without_film_is_plain_fresnel() {
+    let glass = ARgb::new(1.5, 1.5, 1.5);
+    let gold_eta = ARgb::new(0.143, 0.374, 1.442);
+    let gold_k = ARgb::new(3.983, 2.385, 1.603);
+    let no_k = ARgb::default();
+    for cos in [1.0, 0.6, 0.2] {
+        // zero thickness, and film of the same ior as the outside medium
+        for (film_ior, thickness) in [(1.33, 0.0), (1.0, 300.0)] {
+            let film = fresnel_thin_film(cos, 1.0, film_ior, thickness, &glass, &no_k);
+            let bare = fresnel_dielectric(cos, 1.5);
+            for f in film.channels() {
+                assert_approx_eq::assert_approx_eq!(f, bare);
+            }
+            let film = fresnel_thin_film(cos, 1.0, film_ior, thickness, &gold_eta, &gold_k);
+            let bare = fresnel_conductor(cos, &gold_eta, &gold_k);
+            for (f, b) in film.channels().into_iter().zip(bare.channels()) {
+                assert_approx_eq::assert_approx_eq!(f, b);
+            }
+        }
+    }
+    // a soap film in air is coloured, reflectance differs between channels
+    let air = ARgb::new(1.0, 1.0, 1.0);
+    let [r, g, b] = fresnel_thin_film(1.0, 1.0, 1.33, 400.0, &air, &no_k).channels();
+    assert!((r - g).abs() > 0.01 || (g - b).abs() > 0.01);
+}
+
+#[test]
+fn test_charlie_normals_project_to_unit_area() {
+    // integral of D(wm) cos over the hemisphere, midpoint rule in theta
+    for roughness in [0.3, 0.6, 1.0] {
+        let charlie = Charlie::from_roughness(roughness);
+        let steps = 2_000;
+        let dtheta = PI / 2.0 / f64::from(steps);
+        let area: f64 = (0..steps)
+            .map(|i| {
+                let theta = (f64::from(i) + 0.5) * dtheta;
+                let wm = Point::new(theta.sin(), 0.0, theta.cos());
+                charlie.d(&wm) * theta.cos() * theta.sin() * TAU * dtheta
+            })
+            .sum();
+        assert_approx_eq::assert_approx_eq!(area, 1.0, 1e-3);
+    }
+}
@@ -0,0 +1,289 @@
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::core::point3::Point;
+
+const LATTICE: usize = 256;
+// cells repeat every LATTICE along each axis
+const CELL_MASK: i64 = 255;
+
+// Perlin gradient noise: random unit gradients at integer lattice points, blended with
+// hermite smoothing between them. Same seed gives the same pattern on every run.
+pub struct Perlin {
+    gradients: Vec<Point>,
+    perm: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+        let gradients = (0..LATTICE)
+            .map(|_| Point::random_unit_on_sphere(&mut rng))
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..LATTICE).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let perm = [permutation(), permutation(), permutation()];
+        Self { gradients, perm }
+    }
+
+    fn gradient(&self, i: i64, j: i64, k: i64) -> Point {
+        // masking keeps negative cells in range
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let [i, j, k] = [i, j, k].map(|c| (c & CELL_MASK) as usize);
+        self.gradients[self.perm[0][i] ^ self.perm[1][j] ^ self.perm[2][k]]
+    }
+
+    // in [-1, 1], zero at lattice points
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn noise(&self, p: &Point) -> f64 {
+        let cell = p.e.map(f64::floor);
+        let [fx, fy, fz] = [0, 1, 2].map(|a| p.e[a] - cell[a]);
+        let [i, j, k] = cell.map(|c| c as i64);
+        // hermite smoothing of the fractions
+        let [sx, sy, sz] = [fx, fy, fz].map(|f| f * f * (3.0 - 2.0 * f));
+
+        let mut sum = 0.0;
+        // cell offset of the corners, coordinate relative to them and weight in the blend
+        let corners =
+            |f: f64, s: f64| [(0, 0.0, 1.0 - s), (1, 1.0, s)].map(move |(d, c, w)| (d, f - c, w));
+        for (di, ox, wx) in corners(fx, sx) {
+            for (dj, oy, wy) in corners(fy, sy) {
+                for (dk, oz, wz) in corners(fz, sz) {
+                    let offset = Point::new(ox, oy, oz);
+                    let g = self.gradient(i + di, j + dj, k + dk);
+                    sum += wx * wy * wz * g.scalar_prod(&offset);
+                }
+            }
+        }
+        // blend of unit gradients stays within sqrt(3) / 2, stretched to [-1, 1]
+        (sum * f64::sqrt(4.0 / 3.0)).clamp(-1.0, 1.0)
+    }
+
+    // fractal sum of |noise| over octaves, each twice the frequency and half the weight
+    // of the previous one. Non-negative, below 2.
+    #[must_use]
+    pub fn turbulence(&self, p: &Point, octaves: u32) -> f64 {
+        let (mut sum, mut weight, mut p) = (0.0, 1.0, *p);
+        for _ in 0..octaves {
+            sum += weight * self.noise(&p).abs();
+            weight *= 0.5;
+            p = p * 2.0;
+        }
+        sum
+    }
+}
+
+// distance used by cellular noise, changes the shape of the cells
+#[derive(Clone, Copy, Debug)]
+pub enum Metric {
+    // round cells, stone and scales
+    Euclidean,
+    // diamond shaped cells
+    Manhattan,
+    // square cells
+    Chebyshev,
+}
+
+impl Metric {
+    fn distance(self, d: &Point) -> f64 {
+        let [x, y, z] = d.e.map(f64::abs);
+        match self {
+            Metric::Euclidean => f64::sqrt(x * x + y * y + z * z),
+            Metric::Manhattan => x + y + z,
+            Metric::Chebyshev => x.max(y).max(z),
+        }
+    }
+}
+
+// distances to the nearest and second nearest feature point, and the cell of the nearest
+#[derive(Clone, Copy, Debug)]
+pub struct WorleySample {
+    pub f1: f64,
+    pub f2: f64,
+    pub cell: u64,
+}
+
+// Worley (cellular) noise: one feature point jittered inside every unit cell, the value
+// at p is the distance to the closest ones. Feature points are hashed from the seed and
+// the cell, so nothing is stored and the pattern is unbounded.
+pub struct Worley {
+    seed: u64,
+    metric: Metric,
+}
+
+impl Worley {
+    #[must_use]
+    pub fn new(seed: u64, metric: Metric) -> Self {
+        Self { seed, metric }
+    }
+
+    // id of the cell, random bits for its feature point and colour
+    #[allow(clippy::cast_sign_loss)]
+    fn cell_id(&self, cell: [i64; 3]) -> u64 {
+        cell.into_iter()
+            .fold(splitmix64(self.seed), |h, c| splitmix64(h ^ c as u64))
+    }
+
+    // Cells are visited shell by shell around the cell of p. Cells beyond shell r are more
+    // than r away along some axis, so once f2 is within r both distances are exact (the
+    // own point can be up to 3 away under manhattan). Cells whose box is farther than the
+    // current f2 are skipped.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    #[must_use]
+    pub fn sample(&self, p: &Point) -> WorleySample {
+        let base = p.e.map(|c| c.floor() as i64);
+        let mut nearest = WorleySample {
+            f1: f64::INFINITY,
+            f2: f64::INFINITY,
+            cell: 0,
+        };
+        for r in 0_i64.. {
+            for di in -r..=r {
+                for dj in -r..=r {
+                    for dk in -r..=r {
+                        if di.abs().max(dj.abs()).max(dk.abs()) == r {
+                            let cell = [base[0] + di, base[1] + dj, base[2] + dk];
+                            self.visit(p, cell, &mut nearest);
+                        }
+                    }
+                }
+            }
+            if nearest.f2 <= r as f64 {
+                break;
+            }
+        }
+        nearest
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn visit(&self, p: &Point, cell: [i64; 3], nearest: &mut WorleySample) {
+        let corner = cell.map(|c| c as f64);
+        // from p to the closest point of the cell
+        let gap = [0, 1, 2].map(|a| (corner[a] - p.e[a]).max(p.e[a] - corner[a] - 1.0).max(0.0));
+        if self.metric.distance(&Point::new(gap[0], gap[1], gap[2])) >= nearest.f2 {
+            return;
+        }
+        let id = self.cell_id(cell);
+        let [jx, jy, jz] = unit_floats(id);
+        let feature = Point::new(corner[0] + jx, corner[1] + jy, corner[2] + jz);
+        let d = self.metric.distance(&(feature - *p));
+        if d < nearest.f1 {
+            *nearest = WorleySample {
+                f1: d,
+                f2: nearest.f1,
+                cell: id,
+            };
+        } else if d < nearest.f2 {
+            nearest.f2 = d;
+        }
+    }
+}
+
+#[must_use]
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// three numbers in [0, 1) derived from a hash
+#[allow(clippy::cast_precision_loss)]
+#[must_use]
+pub fn unit_floats(hash: u64) -> [f64; 3] {
+    let mut h = hash;
+    [0; 3].map(|_| {
+        h = splitmix64(h);
+        (h >> 11) as f64 / (1_u64 << 53) as f64
+    })
+}
+
+#[test]
+fn test_perlin_is_seeded_and_vanishes_on_lattice() {
+    let noise = Perlin::new(42);
+    let same = Perlin::new(42);
+    let other = Perlin::new(43);
+    let p = Point::new(1.3, -2.7, 0.45);
+    assert_approx_eq::assert_approx_eq!(noise.noise(&p), same.noise(&p));
+    assert!((noise.noise(&p) - other.noise(&p)).abs() > 1e-9);
+
+    for lattice in [Point::default(), Point::new(3.0, -7.0, 255.0)] {
+        assert_approx_eq::assert_approx_eq!(noise.noise(&lattice), 0.0);
+    }
+    let mut min_max = (f64::INFINITY, f64::NEG_INFINITY);
+    for i in 0..1_000 {
+        let t = f64::from(i) * 0.173;
+        let q = Point::new(t, t * 0.37 - 5.0, 2.0 - t * 0.61);
+        let n = noise.noise(&q);
+        min_max = (min_max.0.min(n), min_max.1.max(n));
+        let turbulence = noise.turbulence(&q, 7);
+        assert!((0.0..2.0).contains(&turbulence));
+    }
+    // gradient noise takes both signs
+    assert!(min_max.0 < -0.1 && min_max.1 > 0.1, "{min_max:?}");
+}
+
+#[test]
+fn test_worley_metrics_and_distances_are_ordered() {
+    let metrics = [Metric::Chebyshev, Metric::Euclidean, Metric::Manhattan];
+    for i in 0..200 {
+        let t = f64::from(i) * 0.137;
+        let p = Point::new(t, 3.0 - t * 0.29, t * 0.71 - 8.0);
+        let samples = metrics.map(|metric| Worley::new(7, metric).sample(&p));
+        for s in samples {
+            assert!(0.0 <= s.f1 && s.f1 <= s.f2);
+        }
+        // same feature points, chebyshev <= euclidean <= manhattan for every offset
+        assert!(samples[0].f1 <= samples[1].f1 && samples[1].f1 <= samples[2].f1);
+    }
+    // seeded: same seed same cells, another seed moves the points
+    let p = Point::new(0.5, 0.5, 0.5);
+    let a = Worley::new(7, Metric::Euclidean).sample(&p);
+    let same = Worley::new(7, Metric::Euclidean).sample(&p);
+    let other = Worley::new(8, Metric::Euclidean).sample(&p);
+    assert_eq!(a.cell, same.cell);
+    assert_approx_eq::assert_approx_eq!(a.f1, same.f1);
+    assert!((a.f1 - other.f1).abs() > 1e-9);
+}
+
+#[test]
+fn test_worley_matches_brute_force_search() {
+    // two smallest distances to the feature points of all cells within 4 of p
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn brute_force(worley: &Worley, p: &Point) -> (f64, f64) {
+        let base = p.e.map(|c| c.floor() as i64);
+        let mut distances = Vec::new();
+        for di in -4..=4 {
+            for dj in -4..=4 {
+                for dk in -4..=4 {
+                    let cell = [base[0] + di, base[1] + dj, base[2] + dk];
+                    let [jx, jy, jz] = unit_floats(worley.cell_id(cell));
+                    let feature = Point::new(
+                        cell[0] as f64 + jx,
+                        cell[1] as f64 + jy,
+                        cell[2] as f64 + jz,
+                    );
+                    distances.push(worley.metric.distance(&(feature - *p)));
+                }
+            }
+        }
+        distances.sort_by(f64::total_cmp);
+        (distances[0], distances[1])
+    }
+
+    for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+        let worley = Worley::new(11, metric);
+        for i in 0..2_000 {
+            let t = f64::from(i) * 0.0917;
+            let p = Point::new(t * 1.3 - 40.0, (t * 0.7).sin() * 9.0, 25.0 - t * 0.55);
+            let sample = worley.sample(&p);
+            let (f1, f2) = brute_force(&worley, &p);
+            assert_approx_eq::assert_approx_eq!(sample.f1, f1);
+            assert_approx_eq::assert_approx_eq!(sample.f2, f2);
+        }
+    }
+}
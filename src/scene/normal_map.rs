@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use crate::core::{point3::Point, ray::Ray, rgb::ARgb};
+
+use super::{hittable::HitRec, material::Material, texture::Texture};
+
+// step in texture coordinates for height differences, about a texel of a 1k map
+const BUMP_DELTA: f64 = 1e-3;
+
+pub enum NormalMap {
+    // tangent space normal map: rgb in [0, 1] encodes xyz in [-1, 1], with x along u,
+    // y towards the top of the image (opengl convention) and z along the normal
+    TangentSpace(Arc<dyn Texture>),
+    // height map read from luminance, scale is the height of white in world units
+    Bump(Arc<dyn Texture>, f64),
+}
+
+// Surface detail without geometry: the wrapped material is shaded with a normal
+// perturbed by the map. Needs primitives which set dp/du and dp/dv in HitRec,
+// elsewhere the geometric normal is used.
+pub struct NormalMapped {
+    base: Arc<dyn Material>,
+    map: NormalMap,
+}
+
+impl NormalMapped {
+    pub fn tangent_space(base: &Arc<dyn Material>, map: &Arc<dyn Texture>) -> Self {
+        Self {
+            base: Arc::clone(base),
+            map: NormalMap::TangentSpace(Arc::clone(map)),
+        }
+    }
+
+    pub fn bump(base: &Arc<dyn Material>, height: &Arc<dyn Texture>, scale: f64) -> Self {
+        Self {
+            base: Arc::clone(base),
+            map: NormalMap::Bump(Arc::clone(height), scale),
+        }
+    }
+
+    fn shading_normal(&self, hr: &HitRec) -> Option<Point> {
+        if hr.dpdu.near_zero() || hr.dpdv.near_zero() {
+            return None;
+        }
+        let (u, v) = (hr.tx_coord.u, hr.tx_coord.v);
+        let n = hr.n;
+        let perturbed = match self.map {
+            NormalMap::TangentSpace(ref map) => {
+                let tangent = (hr.dpdu - n * hr.dpdu.scalar_prod(&n)).unit();
+                // bitangent follows dp/dv whichever side of the surface was hit
+                let mut bitangent = n.cross(&tangent);
+                if bitangent.scalar_prod(&hr.dpdv) < 0.0 {
+                    bitangent = -bitangent;
+                }
+                let [x, y, z] = map.color(u, v, &hr.p).channels().map(|c| 2.0 * c - 1.0);
+                tangent * x + bitangent * y + n * z
+            }
+            NormalMap::Bump(ref height, scale) => {
+                // the point moves with the texture coordinates, so solid (3d) textures
+                // work as height maps too
+                let h = |du: f64, dv: f64| {
+                    let p = hr.p + hr.dpdu * du + hr.dpdv * dv;
+                    height.color(u + du, v + dv, &p).luminance() * scale
+                };
+                let h0 = h(0.0, 0.0);
+                let dhdu = (h(BUMP_DELTA, 0.0) - h0) / BUMP_DELTA;
+                let dhdv = (h(0.0, BUMP_DELTA) - h0) / BUMP_DELTA;
+                // derivatives of the displaced surface, change of normal is neglected
+                let dpdu = hr.dpdu + n * dhdu;
+                let dpdv = hr.dpdv + n * dhdv;
+                let bumped = dpdu.cross(&dpdv);
+                if bumped.scalar_prod(&n) < 0.0 {
+                    -bumped
+                } else {
+                    bumped
+                }
+            }
+        };
+        (!perturbed.near_zero()).then(|| perturbed.unit())
+    }
+
+    // hit record with the shading normal, unless it faces away from the ray,
+    // which materials can't handle
+    fn perturbed(&self, r_in: &Ray, hr: &HitRec) -> HitRec {
+        let mut shading = hr.clone();
+        if let Some(n) = self.shading_normal(hr) {
+            if n.scalar_prod(&r_in.dir()) < 0.0 {
+                shading.n = n;
+            }
+        }
+        shading
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        self.base
+            .scatter(r_in, attenuation, scattered, &self.perturbed(r_in, hr))
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        self.base.eval(r_in, wi, &self.perturbed(r_in, hr))
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.base.emitted(u, v, p)
+    }
+
+    fn is_sampled_light(&self) -> bool {
+        self.base.is_sampled_light()
+    }
+}
+
+#[test]
+fn test_bump_tilts_normal_away_from_rising_height() {
+    use super::material::Lambertian;
+
+    // height grows along u
+    struct Ramp;
+    impl Texture for Ramp {
+        fn color(&self, u: f64, _v: f64, _p: &Point) -> ARgb {
+            ARgb::new(u, u, u)
+        }
+    }
+
+    let base: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let ramp: Arc<dyn Texture> = Arc::new(Ramp);
+    let mut hr = HitRec::new(
+        Point::default(),
+        Point::new(0.0, 0.0, 1.0),
+        1.0,
+        Arc::clone(&base),
+    );
+    hr.set_uv((0.5, 0.5));
+    hr.set_tangents(Point::new(1.0, 0.0, 0.0), Point::new(0.0, 1.0, 0.0));
+
+    let n = NormalMapped::bump(&base, &ramp, 1.0)
+        .shading_normal(&hr)
+        .expect("tangents are set");
+    let expected = Point::new(-1.0, 0.0, 1.0).unit();
+    for (a, e) in n.e.iter().zip(expected.e) {
+        assert_approx_eq::assert_approx_eq!(a, e);
+    }
+}
+
+#[test]
+fn test_tangent_space_map_follows_the_tangent_frame() {
+    use super::{hittable::NormalFace, material::Lambertian, texture::SolidColor};
+
+    let base: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let mut hr = HitRec::new(
+        Point::default(),
+        Point::new(0.0, 0.0, 1.0),
+        1.0,
+        Arc::clone(&base),
+    );
+    hr.set_tangents(Point::new(2.0, 0.0, 0.0), Point::new(0.0, 3.0, 0.0));
+    let normal = |color: ARgb, hr: &HitRec| {
+        let map: Arc<dyn Texture> = Arc::new(SolidColor::new(color));
+        NormalMapped::tangent_space(&base, &map)
+            .shading_normal(hr)
+            .expect("tangents are set")
+    };
+    let assert_close = |a: Point, e: Point| {
+        for (a, e) in a.e.iter().zip(e.e) {
+            assert_approx_eq::assert_approx_eq!(a, e);
+        }
+    };
+
+    // flat map keeps the normal, x goes along dp/du and y along dp/dv
+    assert_close(normal(ARgb::new(0.5, 0.5, 1.0), &hr), hr.n);
+    assert_close(
+        normal(ARgb::new(0.8, 0.5, 0.9), &hr),
+        Point::new(0.6, 0.0, 0.8),
+    );
+    assert_close(
+        normal(ARgb::new(0.5, 0.8, 0.9), &hr),
+        Point::new(0.0, 0.6, 0.8),
+    );
+
+    // seen from the back the normal is flipped, y still follows dp/dv
+    hr.n = Point::new(0.0, 0.0, -1.0);
+    hr.face = NormalFace::Inside;
+    assert_close(
+        normal(ARgb::new(0.5, 0.8, 0.9), &hr),
+        Point::new(0.0, 0.6, -0.8),
+    );
+
+    // no tangents, no perturbation
+    hr.set_tangents(Point::default(), Point::default());
+    let flat: Arc<dyn Texture> = Arc::new(SolidColor::new(ARgb::new(0.8, 0.5, 0.9)));
+    assert!(NormalMapped::tangent_space(&base, &flat)
+        .shading_normal(&hr)
+        .is_none());
+}
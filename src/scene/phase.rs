@@ -0,0 +1,162 @@
+use std::{
+    f64::consts::{PI, TAU},
+    sync::Arc,
+};
+
+use rand::{Rng, RngCore};
+
+use crate::core::{onb::Onb, point3::Point, ray::Ray, rgb::ARgb};
+
+use super::{
+    hittable::HitRec,
+    material::Material,
+    texture::{SolidColor, Texture},
+};
+
+// Angular distribution of light scattered inside of a medium.
+// dir_in is the (unit) direction the ray travels in, dir_out is the scattered one,
+// so forward scattering keeps them close.
+pub trait PhaseFunction: Send + Sync {
+    fn p(&self, dir_in: &Point, dir_out: &Point) -> f64;
+    // dir_out distributed exactly as p, so the sampling weight is always 1
+    fn sample(&self, dir_in: &Point, rng: &mut dyn RngCore) -> Point;
+}
+
+pub struct IsotropicPhase;
+
+impl PhaseFunction for IsotropicPhase {
+    fn p(&self, _dir_in: &Point, _dir_out: &Point) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample(&self, _dir_in: &Point, rng: &mut dyn RngCore) -> Point {
+        Point::random_unit_on_sphere(rng)
+    }
+}
+
+// g in (-1, 1): positive is forward scattering (haze), negative is back scattering (dust),
+// zero is isotropic
+pub struct HenyeyGreenstein {
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    #[must_use]
+    pub fn new(g: f64) -> Self {
+        Self {
+            g: g.clamp(-0.99, 0.99),
+        }
+    }
+
+    fn p_cos(&self, cos: f64) -> f64 {
+        let g = self.g;
+        let denom = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p(&self, dir_in: &Point, dir_out: &Point) -> f64 {
+        self.p_cos(dir_in.scalar_prod(dir_out))
+    }
+
+    fn sample(&self, dir_in: &Point, rng: &mut dyn RngCore) -> Point {
+        let g = self.g;
+        let xi: f64 = rng.random();
+        // inverted cdf of hg over cos theta
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            ((1.0 + g * g - sq * sq) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = f64::sqrt(f64::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = TAU * rng.random::<f64>();
+        Onb::from_w(dir_in).local_to_world(&Point::new(
+            sin_theta * phi.cos(),
+            sin_theta * phi.sin(),
+            cos_theta,
+        ))
+    }
+}
+
+// Material for scattering events inside of media: albedo (scattering / extinction)
+// tinted by weight, which media use for their own sampling corrections
+pub struct PhaseMaterial {
+    albedo: Arc<dyn Texture>,
+    phase: Arc<dyn PhaseFunction>,
+    weight: ARgb,
+}
+
+impl PhaseMaterial {
+    pub fn new(albedo: &Arc<dyn Texture>, phase: Arc<dyn PhaseFunction>) -> Self {
+        Self::weighted(albedo, phase, ARgb::new(1.0, 1.0, 1.0))
+    }
+
+    #[must_use]
+    pub fn isotropic(albedo: ARgb) -> Self {
+        let albedo: Arc<dyn Texture> = Arc::new(SolidColor::new(albedo));
+        Self::new(&albedo, Arc::new(IsotropicPhase))
+    }
+
+    pub fn weighted(
+        albedo: &Arc<dyn Texture>,
+        phase: Arc<dyn PhaseFunction>,
+        weight: ARgb,
+    ) -> Self {
+        Self {
+            albedo: Arc::clone(albedo),
+            phase,
+            weight,
+        }
+    }
+
+    fn albedo(&self, hr: &HitRec) -> ARgb {
+        self.albedo.color(hr.tx_coord.u, hr.tx_coord.v, &hr.p) * self.weight
+    }
+}
+
+impl Material for PhaseMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let dir = self.phase.sample(&r_in.dir().unit(), &mut rand::rng());
+        *scattered = r_in.continued(hr.p, dir);
+        *attenuation = self.albedo(hr);
+        true
+    }
+
+    // no cosine term for media
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        Some(self.albedo(hr) * self.phase.p(&r_in.dir().unit(), wi))
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn test_henyey_greenstein_mean_cosine_is_g() {
+    use rand::SeedableRng;
+    use rand_xoshiro::Xoshiro256PlusPlus;
+
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(5);
+    let dir_in = Point::new(0.0, 0.0, 1.0);
+    for g in [-0.6, 0.0, 0.3, 0.85] {
+        let hg = HenyeyGreenstein::new(g);
+        let n = 200_000;
+        let mean_cos = (0..n)
+            .map(|_| hg.sample(&dir_in, &mut rng).scalar_prod(&dir_in))
+            .sum::<f64>()
+            / f64::from(n);
+        assert!(
+            (mean_cos - g).abs() < 0.01,
+            "g = {g}, mean cos = {mean_cos}"
+        );
+    }
+}
@@ -0,0 +1,749 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::{Rng, RngCore};
+
+use crate::core::{
+    onb::Onb,
+    point3::Point,
+    ray::{MediumEntry, Ray},
+    rgb::ARgb,
+};
+
+use super::{
+    hittable::{AlphaMode, HitRec, NormalFace, Opacity},
+    material::{Crossing, Material},
+    microfacet::{self, Charlie, TrowbridgeReitz},
+    texture::{SolidColor, Texture},
+};
+
+// Inputs of the principled material. Scalar inputs are read from the luminance of their
+// texture, so plain grey textures (or solid colours) work as maps.
+pub struct PrincipledParams {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    // dielectric reflectance, 0.5 is the common 4% at normal incidence
+    pub specular: Arc<dyn Texture>,
+    // colour of the grazing retro-reflection of cloth, black disables it
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_roughness: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub ior: f64,
+    pub emission: Arc<dyn Texture>,
+    pub emission_strength: f64,
+}
+
+fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(ARgb::new(value, value, value)))
+}
+
+fn color(value: ARgb) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::new(value))
+}
+
+impl Default for PrincipledParams {
+    fn default() -> Self {
+        Self {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            sheen: constant(0.0),
+            sheen_roughness: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_roughness: constant(0.03),
+            transmission: constant(0.0),
+            ior: 1.5,
+            emission: constant(0.0),
+            emission_strength: 1.0,
+        }
+    }
+}
+
+// colour of a texture multiplied by factor, e.g. gltf base colour factor times texture
+struct Scaled {
+    texture: Arc<dyn Texture>,
+    factor: ARgb,
+}
+
+impl Texture for Scaled {
+    fn color(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.texture.color(u, v, p) * self.factor
+    }
+}
+
+// one channel of a packed texture as grey, e.g. gltf metallic-roughness map
+struct Channel {
+    texture: Arc<dyn Texture>,
+    index: usize,
+    factor: f64,
+}
+
+impl Texture for Channel {
+    fn color(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        let value = self.texture.color(u, v, p).channels()[self.index] * self.factor;
+        ARgb::new(value, value, value)
+    }
+}
+
+fn scaled(texture: Option<&Arc<dyn Texture>>, factor: ARgb) -> Arc<dyn Texture> {
+    match texture {
+        Some(texture) => Arc::new(Scaled {
+            texture: Arc::clone(texture),
+            factor,
+        }),
+        None => color(factor),
+    }
+}
+
+fn channel(texture: Option<&Arc<dyn Texture>>, channel: usize, factor: f64) -> Arc<dyn Texture> {
+    match texture {
+        Some(texture) => Arc::new(Channel {
+            texture: Arc::clone(texture),
+            index: channel,
+            factor,
+        }),
+        None => constant(factor),
+    }
+}
+
+// glTF 2.0 metallic-roughness material with the clearcoat, sheen, transmission, ior,
+// specular and emissive strength extensions. Factors multiply their textures
+// as in the specification.
+pub struct GltfMaterial {
+    pub base_color_factor: ARgb,
+    pub base_color_texture: Option<Arc<dyn Texture>>,
+    pub metallic_factor: f64,
+    pub roughness_factor: f64,
+    // roughness in green, metallic in blue
+    pub metallic_roughness_texture: Option<Arc<dyn Texture>>,
+    pub emissive_factor: ARgb,
+    pub emissive_texture: Option<Arc<dyn Texture>>,
+    pub emissive_strength: f64,
+    pub specular_factor: f64,
+    pub sheen_color_factor: ARgb,
+    pub sheen_roughness_factor: f64,
+    pub clearcoat_factor: f64,
+    pub clearcoat_roughness_factor: f64,
+    pub transmission_factor: f64,
+    pub ior: f64,
+}
+
+// defaults of the specification
+impl Default for GltfMaterial {
+    fn default() -> Self {
+        Self {
+            base_color_factor: ARgb::new(1.0, 1.0, 1.0),
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            emissive_factor: ARgb::default(),
+            emissive_texture: None,
+            emissive_strength: 1.0,
+            specular_factor: 1.0,
+            sheen_color_factor: ARgb::default(),
+            sheen_roughness_factor: 0.0,
+            clearcoat_factor: 0.0,
+            clearcoat_roughness_factor: 0.0,
+            transmission_factor: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+impl From<&GltfMaterial> for PrincipledParams {
+    fn from(m: &GltfMaterial) -> Self {
+        // gltf derives dielectric reflectance from ior, specular 0.5 means 4%
+        let f0 = ((m.ior - 1.0) / (m.ior + 1.0)).powi(2);
+        Self {
+            base_color: scaled(m.base_color_texture.as_ref(), m.base_color_factor),
+            metallic: channel(m.metallic_roughness_texture.as_ref(), 2, m.metallic_factor),
+            roughness: channel(m.metallic_roughness_texture.as_ref(), 1, m.roughness_factor),
+            specular: constant((f0 / 0.08 * m.specular_factor).clamp(0.0, 1.0)),
+            sheen: color(m.sheen_color_factor),
+            sheen_roughness: constant(m.sheen_roughness_factor),
+            clearcoat: constant(m.clearcoat_factor),
+            clearcoat_roughness: constant(m.clearcoat_roughness_factor),
+            transmission: constant(m.transmission_factor),
+            ior: m.ior,
+            emission: scaled(m.emissive_texture.as_ref(), m.emissive_factor),
+            emission_strength: m.emissive_strength,
+        }
+    }
+}
+
+// Wavefront MTL material, classic Phong parameters with the common PBR extension
+// (Pr, Pm, Ps, Pc, Pcr). PBR values take precedence where present.
+pub struct MtlMaterial {
+    pub kd: ARgb,
+    pub map_kd: Option<Arc<dyn Texture>>,
+    pub ks: ARgb,
+    pub ns: f64,
+    pub ni: f64,
+    // dissolve: coverage (alpha) of the surface, 0 is fully transparent, see opacity
+    pub d: f64,
+    pub ke: ARgb,
+    pub pr: Option<f64>,
+    pub pm: Option<f64>,
+    pub ps: Option<ARgb>,
+    pub pc: Option<f64>,
+    pub pcr: Option<f64>,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            kd: ARgb::new(0.8, 0.8, 0.8),
+            map_kd: None,
+            ks: ARgb::default(),
+            ns: 10.0,
+            ni: 1.5,
+            d: 1.0,
+            ke: ARgb::default(),
+            pr: None,
+            pm: None,
+            ps: None,
+            pc: None,
+            pcr: None,
+        }
+    }
+}
+
+impl From<&MtlMaterial> for PrincipledParams {
+    fn from(m: &MtlMaterial) -> Self {
+        // alpha matching the width of a phong lobe with exponent ns,
+        // roughness is its square root
+        let roughness =
+            m.pr.unwrap_or_else(|| f64::powf(2.0 / (m.ns.max(0.0) + 2.0), 0.25));
+        // ks is reflectance at normal incidence, specular 1 means 8%
+        let specular = if m.pr.is_some() {
+            0.5
+        } else {
+            (m.ks.luminance() / 0.08).clamp(0.0, 1.0)
+        };
+        Self {
+            base_color: scaled(m.map_kd.as_ref(), m.kd),
+            metallic: constant(m.pm.unwrap_or(0.0)),
+            roughness: constant(roughness),
+            specular: constant(specular),
+            sheen: color(m.ps.unwrap_or_default()),
+            sheen_roughness: constant(0.5),
+            clearcoat: constant(m.pc.unwrap_or(0.0)),
+            clearcoat_roughness: constant(m.pcr.unwrap_or(0.03)),
+            transmission: constant(0.0),
+            ior: m.ni,
+            emission: color(m.ke),
+            emission_strength: 1.0,
+        }
+    }
+}
+
+// constant coverage for the opacity of dissolved mtl materials
+struct Dissolve(f64);
+
+impl Texture for Dissolve {
+    fn color(&self, _u: f64, _v: f64, _p: &Point) -> ARgb {
+        ARgb::new(1.0, 1.0, 1.0)
+    }
+
+    fn alpha(&self, _u: f64, _v: f64, _p: &Point) -> f64 {
+        self.0
+    }
+}
+
+impl MtlMaterial {
+    // dissolve is coverage rather than refraction, it goes to the primitives using the
+    // material as stochastic opacity. None for opaque materials.
+    #[must_use]
+    pub fn opacity(&self) -> Option<Opacity> {
+        let d = self.d.clamp(0.0, 1.0);
+        (d < 1.0).then(|| {
+            let coverage: Arc<dyn Texture> = Arc::new(Dissolve(d));
+            Opacity::new(&coverage, AlphaMode::Stochastic)
+        })
+    }
+}
+
+// parameters looked up at one shading point
+struct Inputs {
+    base_color: ARgb,
+    metallic: f64,
+    specular: f64,
+    sheen: ARgb,
+    sheen_distribution: Charlie,
+    clearcoat: f64,
+    transmission: f64,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    // roughness again, burley diffuse uses it directly
+    roughness: f64,
+}
+
+#[derive(Clone, Copy)]
+enum Lobe {
+    Diffuse,
+    Sheen,
+    Specular,
+    Clearcoat,
+    Glass,
+}
+
+const LOBES: [Lobe; 5] = [
+    Lobe::Diffuse,
+    Lobe::Sheen,
+    Lobe::Specular,
+    Lobe::Clearcoat,
+    Lobe::Glass,
+];
+
+// perfectly smooth lobes can't be evaluated for light sampling, the integrator would
+// miss lights reflected by them
+const MIN_ROUGHNESS: f64 = 0.04;
+
+fn schlick(f0: ARgb, cos: f64) -> ARgb {
+    let w = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    f0 * (1.0 - w) + ARgb::new(w, w, w)
+}
+
+fn lerp(a: ARgb, b: ARgb, t: f64) -> ARgb {
+    a * (1.0 - t) + b * t
+}
+
+// Disney-style principled BSDF: burley diffuse, charlie sheen, GGX specular (dielectric or
+// metallic), GGX clearcoat and rough glass transmission, mixed as in the 2015 Disney
+// model. One lobe is sampled per scattering event, picked proportionally to an estimate
+// of its contribution.
+pub struct Principled {
+    params: PrincipledParams,
+}
+
+impl Principled {
+    #[must_use]
+    pub fn new(params: PrincipledParams) -> Self {
+        Self { params }
+    }
+
+    fn inputs(&self, hr: &HitRec) -> Inputs {
+        let (u, v, p) = (hr.tx_coord.u, hr.tx_coord.v, &hr.p);
+        let scalar = |t: &Arc<dyn Texture>| t.color(u, v, p).luminance().clamp(0.0, 1.0);
+        let roughness = scalar(&self.params.roughness).max(MIN_ROUGHNESS);
+        let clearcoat_roughness = scalar(&self.params.clearcoat_roughness).max(MIN_ROUGHNESS);
+        Inputs {
+            base_color: self.params.base_color.color(u, v, p),
+            metallic: scalar(&self.params.metallic),
+            specular: scalar(&self.params.specular),
+            sheen: self.params.sheen.color(u, v, p),
+            sheen_distribution: Charlie::from_roughness(scalar(&self.params.sheen_roughness)),
+            clearcoat: scalar(&self.params.clearcoat),
+            transmission: scalar(&self.params.transmission),
+            distribution: TrowbridgeReitz::from_roughness(roughness, 0.0),
+            clearcoat_distribution: TrowbridgeReitz::from_roughness(clearcoat_roughness, 0.0),
+            roughness,
+        }
+    }
+
+    fn entry(&self) -> MediumEntry {
+        MediumEntry {
+            id: std::ptr::from_ref(self).addr(),
+            priority: 0,
+            ior: self.params.ior,
+            absorption: None,
+        }
+    }
+
+    // rays inside of a transmissive object only see the glass interface,
+    // the other layers are on the outside
+    fn inside_glass(hr: &HitRec, inputs: &Inputs) -> bool {
+        matches!(hr.face, NormalFace::Inside) && inputs.transmission > 0.0
+    }
+
+    // scale of each lobe and probability of picking it for sampling
+    fn lobe_weights(inputs: &Inputs, cos_o: f64, inside_glass: bool) -> [(f64, f64); 5] {
+        if inside_glass {
+            return [(0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (0.0, 0.0), (1.0, 1.0)];
+        }
+        let dielectric = 1.0 - inputs.metallic;
+        let diffuse = dielectric * (1.0 - inputs.transmission);
+        let glass = dielectric * inputs.transmission;
+        let specular = 1.0 - glass;
+        let clearcoat = 0.25 * inputs.clearcoat;
+        let f_specular = schlick(Self::specular_f0(inputs), cos_o).luminance();
+        let f_clearcoat = schlick(ARgb::new(0.04, 0.04, 0.04), cos_o).luminance();
+
+        let through = 1.0 - Self::dielectric_fresnel(inputs, cos_o);
+        let under_coat = Self::coat_through(inputs, cos_o);
+
+        let weights = [
+            (
+                diffuse,
+                diffuse * under_coat * through * inputs.base_color.luminance(),
+            ),
+            (diffuse, diffuse * under_coat * inputs.sheen.luminance()),
+            (specular, specular * under_coat * f_specular),
+            (clearcoat, clearcoat * f_clearcoat),
+            (glass, glass * under_coat),
+        ];
+        let total: f64 = weights.iter().map(|(_, p)| p).sum();
+        if total <= 0.0 {
+            return [(0.0, 0.0); 5];
+        }
+        weights.map(|(scale, p)| (scale, p / total))
+    }
+
+    // light reflected by the clearcoat doesn't reach the layers below it, on the way in
+    // nor out, which keeps coat and base together below one
+    fn coat_through(inputs: &Inputs, cos: f64) -> f64 {
+        1.0 - 0.25 * inputs.clearcoat * schlick(ARgb::new(0.04, 0.04, 0.04), cos).luminance()
+    }
+
+    fn dielectric_fresnel(inputs: &Inputs, cos: f64) -> f64 {
+        let f0 = 0.08 * inputs.specular;
+        schlick(ARgb::new(f0, f0, f0), cos).luminance()
+    }
+
+    fn specular_f0(inputs: &Inputs) -> ARgb {
+        let dielectric = 0.08 * inputs.specular;
+        lerp(
+            ARgb::new(dielectric, dielectric, dielectric),
+            inputs.base_color,
+            inputs.metallic,
+        )
+    }
+
+    // reflection lobes times cos, both directions in the upper hemisphere
+    fn eval_lobe(lobe: Lobe, inputs: &Inputs, wo: &Point, wi: &Point, eta: f64) -> ARgb {
+        let wm = (*wo + *wi).unit();
+        let cos_d = wi.scalar_prod(&wm);
+        match lobe {
+            // light reflected by the dielectric specular never reaches the diffuse layer
+            // below it, on the way in nor out (as through the coat of Coated)
+            Lobe::Diffuse => {
+                let fd90 = 0.5 + 2.0 * inputs.roughness * cos_d * cos_d;
+                let fd = |cos: f64| 1.0 + (fd90 - 1.0) * (1.0 - cos).powi(5);
+                let through = (1.0 - Self::dielectric_fresnel(inputs, wo.z()))
+                    * (1.0 - Self::dielectric_fresnel(inputs, wi.z()));
+                inputs.base_color * (through * fd(wi.z()) * fd(wo.z()) * wi.z() / PI)
+            }
+            Lobe::Sheen => inputs.sheen * inputs.sheen_distribution.eval(wo, wi),
+            Lobe::Specular => {
+                let d = &inputs.distribution;
+                schlick(Self::specular_f0(inputs), cos_d)
+                    * (d.d(&wm) * d.g(wo, wi) / (4.0 * wo.z()))
+            }
+            Lobe::Clearcoat => {
+                let d = &inputs.clearcoat_distribution;
+                schlick(ARgb::new(0.04, 0.04, 0.04), cos_d)
+                    * (d.d(&wm) * d.g(wo, wi) / (4.0 * wo.z()))
+            }
+            Lobe::Glass => {
+                let d = &inputs.distribution;
+                let f = microfacet::fresnel_dielectric(wo.scalar_prod(&wm), eta);
+                let brdf = f * d.d(&wm) * d.g(wo, wi) / (4.0 * wo.z());
+                ARgb::new(brdf, brdf, brdf)
+            }
+        }
+    }
+
+    // sampled direction and its weight (bsdf * cos / pdf) for a single lobe,
+    // None if the sample is absorbed. Transmitted directions come with the media
+    // of the ray after crossing.
+    fn sample_lobe(
+        lobe: Lobe,
+        inputs: &Inputs,
+        wo: &Point,
+        eta: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Point, ARgb, bool)> {
+        let up = Point::new(0.0, 0.0, 1.0);
+        match lobe {
+            // cosine sampling, pdf is cos / pi
+            Lobe::Diffuse | Lobe::Sheen => {
+                let wi = Point::random_cosine_on_hemisphere(rng, &up);
+                (wi.z() > 0.0).then(|| {
+                    let f = Self::eval_lobe(lobe, inputs, wo, &wi, eta);
+                    (wi, f * (PI / wi.z()), false)
+                })
+            }
+            Lobe::Specular | Lobe::Clearcoat => {
+                let d = match lobe {
+                    Lobe::Specular => &inputs.distribution,
+                    _ => &inputs.clearcoat_distribution,
+                };
+                let wm = d.sample_wm(wo, rng);
+                let wi = microfacet::reflect(wo, &wm);
+                if wi.z() <= 0.0 {
+                    return None;
+                }
+                let f0 = match lobe {
+                    Lobe::Specular => Self::specular_f0(inputs),
+                    _ => ARgb::new(0.04, 0.04, 0.04),
+                };
+                let weight = schlick(f0, wo.scalar_prod(&wm)) * (d.g(wo, &wi) / d.g1(wo));
+                Some((wi, weight, false))
+            }
+            // fresnel picks between reflection and refraction, so only G / G1 is left
+            Lobe::Glass => {
+                let d = &inputs.distribution;
+                let wm = d.sample_wm(wo, rng);
+                let cos_o = wo.scalar_prod(&wm);
+                let refracted = microfacet::refract(wo, &wm, eta)
+                    .filter(|_| rng.random::<f64>() >= microfacet::fresnel_dielectric(cos_o, eta));
+                match refracted {
+                    Some(wi) if wi.z() < 0.0 => {
+                        let g = d.g(wo, &wi) / d.g1(wo);
+                        Some((wi, inputs.base_color * g, true))
+                    }
+                    Some(_) => None,
+                    None => {
+                        let wi = microfacet::reflect(wo, &wm);
+                        let g = d.g(wo, &wi) / d.g1(wo);
+                        (wi.z() > 0.0).then(|| (wi, ARgb::new(g, g, g), false))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let inputs = self.inputs(hr);
+        let inside_glass = Self::inside_glass(hr, &inputs);
+        let weights = Self::lobe_weights(&inputs, wo.z(), inside_glass);
+
+        let mut rng = rand::rng();
+        let mut xi = rng.random::<f64>();
+        let mut picked = None;
+        for (lobe, (scale, p)) in LOBES.into_iter().zip(weights) {
+            if p <= 0.0 {
+                continue;
+            }
+            // the last candidate also takes whatever rounding left over
+            picked = Some((lobe, scale, p));
+            xi -= p;
+            if xi < 0.0 {
+                break;
+            }
+        }
+        let Some((lobe, scale, p)) = picked else {
+            return false;
+        };
+
+        let crossing = Crossing::resolve(self.entry(), r_in, hr);
+        let eta = match crossing.etas {
+            Some((eta_i, eta_t)) => eta_t / eta_i,
+            // inside of a higher priority volume, this surface is not an interface
+            None if matches!(lobe, Lobe::Glass) => {
+                *scattered = r_in.continued(hr.p, r_in.dir()).with_media(&crossing.next);
+                *attenuation = ARgb::new(1.0, 1.0, 1.0);
+                return true;
+            }
+            None => 1.0,
+        };
+
+        let Some((wi, weight, transmitted)) = Self::sample_lobe(lobe, &inputs, &wo, eta, &mut rng)
+        else {
+            return false;
+        };
+        let ray = r_in.continued(hr.p, onb.local_to_world(&wi));
+        let under_coat = match lobe {
+            Lobe::Clearcoat => 1.0,
+            _ if inside_glass => 1.0,
+            // the other side of the object has no coat
+            _ if transmitted => Self::coat_through(&inputs, wo.z()),
+            _ => Self::coat_through(&inputs, wo.z()) * Self::coat_through(&inputs, wi.z()),
+        };
+        *scattered = if transmitted {
+            ray.with_media(&crossing.next)
+        } else {
+            ray
+        };
+        *attenuation = weight * (scale * under_coat / p);
+        true
+    }
+
+    // sum of all reflection lobes, transmitted directions are left to scattered rays
+    // as with RoughDielectric
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let inputs = self.inputs(hr);
+        let inside_glass = Self::inside_glass(hr, &inputs);
+        let eta = Crossing::resolve(self.entry(), r_in, hr)
+            .etas
+            .map_or(1.0, |(eta_i, eta_t)| eta_t / eta_i);
+        let under_coat = if inside_glass {
+            1.0
+        } else {
+            Self::coat_through(&inputs, wo.z()) * Self::coat_through(&inputs, wi.z())
+        };
+        let f = LOBES
+            .into_iter()
+            .zip(Self::lobe_weights(&inputs, wo.z(), inside_glass))
+            .filter(|(_, (scale, _))| *scale > 0.0)
+            .fold(ARgb::default(), |acc, (lobe, (scale, _))| {
+                let scale = match lobe {
+                    Lobe::Clearcoat => scale,
+                    _ => scale * under_coat,
+                };
+                acc + Self::eval_lobe(lobe, &inputs, &wo, &wi, eta) * scale
+            });
+        Some(f)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        self.params.emission.color(u, v, p) * self.params.emission_strength
+    }
+}
+
+#[test]
+fn test_gltf_import_unpacks_metallic_roughness() {
+    let packed: Arc<dyn Texture> = color(ARgb::new(0.0, 0.6, 0.2));
+    let params = PrincipledParams::from(&GltfMaterial {
+        metallic_factor: 0.5,
+        metallic_roughness_texture: Some(packed),
+        ..GltfMaterial::default()
+    });
+    let p = Point::default();
+    assert_approx_eq::assert_approx_eq!(params.roughness.color(0.0, 0.0, &p).luminance(), 0.6);
+    assert_approx_eq::assert_approx_eq!(params.metallic.color(0.0, 0.0, &p).luminance(), 0.1);
+    // ior 1.5 reflects 4% at normal incidence, which is specular 0.5
+    assert_approx_eq::assert_approx_eq!(params.specular.color(0.0, 0.0, &p).luminance(), 0.5);
+}
+
+#[cfg(test)]
+fn mean_scatter(material: &Principled, degrees: f64, count: u32) -> (f64, f64) {
+    // (mean weight of all scattered rays, share of rays going below the surface)
+    let n = Point::new(0.0, 0.0, 1.0);
+    let theta = degrees.to_radians();
+    let dir = Point::new(theta.sin(), 0.0, -theta.cos());
+    let r_in = Ray::new(-dir, dir, None);
+    let mut hr = HitRec::new(
+        Point::default(),
+        n,
+        1.0,
+        Arc::new(Principled::new(PrincipledParams::default())),
+    );
+    hr.set_face_normal(&r_in, &n);
+    let (mut total, mut below) = (0.0, 0);
+    for _ in 0..count {
+        let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+        if material.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+            total += attenuation.luminance();
+            if scattered.dir().scalar_prod(&n) < 0.0 {
+                below += 1;
+            }
+        }
+    }
+    (
+        total / f64::from(count),
+        f64::from(below) / f64::from(count),
+    )
+}
+
+#[test]
+fn test_principled_white_furnace_stays_below_one() {
+    for roughness in [0.1, 0.5, 1.0] {
+        for (specular, clearcoat) in [(0.5, 0.0), (1.0, 0.0), (0.5, 1.0), (1.0, 1.0)] {
+            let material = Principled::new(PrincipledParams {
+                base_color: constant(1.0),
+                roughness: constant(roughness),
+                specular: constant(specular),
+                clearcoat: constant(clearcoat),
+                clearcoat_roughness: constant(0.05),
+                ..PrincipledParams::default()
+            });
+            for degrees in [0.0, 45.0, 75.0, 85.0] {
+                let (albedo, _) = mean_scatter(&material, degrees, 20_000);
+                assert!(
+                    albedo <= 1.0 + 0.02,
+                    "{roughness} {specular} {clearcoat} {degrees} {albedo}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_principled_metal_has_no_diffuse_and_glass_transmits() {
+    let n = Point::new(0.0, 0.0, 1.0);
+    let hr = HitRec::new(
+        Point::default(),
+        n,
+        1.0,
+        Arc::new(Principled::new(PrincipledParams::default())),
+    );
+    let metal = Principled::new(PrincipledParams {
+        base_color: color(ARgb::new(0.9, 0.6, 0.2)),
+        metallic: constant(1.0),
+        ..PrincipledParams::default()
+    });
+    let weights = Principled::lobe_weights(&metal.inputs(&hr), 0.7, false);
+    // diffuse and sheen lobes are switched off, only specular is left
+    assert_eq!(weights[0], (0.0, 0.0));
+    assert_eq!(weights[1], (0.0, 0.0));
+    assert_approx_eq::assert_approx_eq!(weights[2].1, 1.0);
+    // eval is the specular lobe alone
+    let r_in = Ray::new(n, -n, None);
+    let onb = Onb::from_w(&n);
+    let wo = onb.world_to_local(&n);
+    for wi in [
+        Point::new(0.9, 0.0, 0.1).unit(),
+        Point::new(0.3, 0.4, 0.8).unit(),
+    ] {
+        let f = metal.eval(&r_in, &wi, &hr).expect("metal is lit");
+        let wi = onb.world_to_local(&wi);
+        let specular = Principled::eval_lobe(Lobe::Specular, &metal.inputs(&hr), &wo, &wi, 1.5);
+        for (a, e) in f.channels().into_iter().zip(specular.channels()) {
+            assert_approx_eq::assert_approx_eq!(a, e);
+        }
+    }
+
+    // clear glass sends most light through, fresnel reflects the rest
+    let glass = Principled::new(PrincipledParams {
+        base_color: constant(1.0),
+        roughness: constant(0.0),
+        transmission: constant(1.0),
+        ..PrincipledParams::default()
+    });
+    let (albedo, below) = mean_scatter(&glass, 0.0, 20_000);
+    assert!((below - 0.96).abs() < 0.01, "{below}");
+    assert!(albedo <= 1.0 + 1e-9 && albedo > 0.95, "{albedo}");
+}
+
+#[test]
+fn test_mtl_dissolve_is_coverage_not_transmission() {
+    let p = Point::default();
+    let glass_like = MtlMaterial {
+        d: 0.3,
+        ..MtlMaterial::default()
+    };
+    let params = PrincipledParams::from(&glass_like);
+    assert_approx_eq::assert_approx_eq!(params.transmission.color(0.0, 0.0, &p).luminance(), 0.0);
+    let opacity = glass_like
+        .opacity()
+        .expect("dissolved material has opacity");
+    let kept = (0..10_000).filter(|_| opacity.keeps(0.0, 0.0, &p)).count();
+    #[allow(clippy::cast_precision_loss)]
+    let coverage = kept as f64 / 10_000.0;
+    assert!((coverage - 0.3).abs() < 0.02, "{coverage}");
+    assert!(MtlMaterial::default().opacity().is_none());
+}
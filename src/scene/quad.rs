@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use crate::{
+    core::{point3::Point, ray::Ray},
+    utils::interval::Interval,
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRec, Hittable, Opacity},
+    material::Material,
+};
+
+// thickness given to bounding boxes of flat primitives
+pub const FLAT_PAD: f64 = 1e-4;
+
+// Parallelogram with corner q and edges u and v. Front (outside) is where u x v points,
+// texture coordinates run along the edges from 0 to 1.
+pub struct Quad {
+    q: Point,
+    u: Point,
+    v: Point,
+    normal: Point,
+    // plane is normal . p = d
+    d: f64,
+    // n / (n . n), projects hit points to edge coordinates
+    w: Point,
+    mat: Arc<dyn Material>,
+    bbox: Aabb,
+    opacity: Option<Opacity>,
+}
+
+impl Quad {
+    pub fn new(q: Point, u: Point, v: Point, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit();
+        let diagonal = Aabb::from_points(&q, &(q + u + v));
+        let other_diagonal = Aabb::from_points(&(q + u), &(q + v));
+        Self {
+            q,
+            u,
+            v,
+            normal,
+            d: normal.scalar_prod(&q),
+            w: n / n.scalar_prod(&n),
+            mat,
+            bbox: diagonal.expand(&other_diagonal).padded(FLAT_PAD),
+            opacity: None,
+        }
+    }
+
+    // alpha cutout, e.g. leaf and fence cards
+    #[must_use]
+    pub fn with_opacity(mut self, opacity: Opacity) -> Self {
+        self.opacity = Some(opacity);
+        self
+    }
+
+    // t and edge coordinates of the hit inside ray_t_possible, cut out parts are missed
+    fn intersect(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<(f64, f64, f64)> {
+        let denom = self.normal.scalar_prod(&ray.dir());
+        // parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.scalar_prod(&ray.orig())) / denom;
+        if !ray_t_possible.surrounds(t) {
+            return None;
+        }
+        let planar = ray.at(t) - self.q;
+        let alpha = self.w.scalar_prod(&planar.cross(&self.v));
+        let beta = self.w.scalar_prod(&self.u.cross(&planar));
+        let unit = Interval::new(0.0, 1.0);
+        if !(unit.contains(alpha) && unit.contains(beta)) {
+            return None;
+        }
+        let kept = self
+            .opacity
+            .as_ref()
+            .is_none_or(|opacity| opacity.keeps(alpha, beta, &ray.at(t)));
+        kept.then_some((t, alpha, beta))
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        let (t, alpha, beta) = self.intersect(ray, ray_t_possible)?;
+        let mut hr = HitRec::new(ray.at(t), self.normal, t, Arc::clone(&self.mat));
+        hr.set_face_normal(ray, &self.normal);
+        hr.set_uv((alpha, beta));
+        hr.set_tangents(self.u, self.v);
+        Some(hr)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.intersect(ray, ray_t_possible).is_some()
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
+}
+
+#[test]
+fn test_two_sided_quad_shows_back_material_from_behind() {
+    use super::material::{Lambertian, TwoSided};
+    use crate::core::rgb::ARgb;
+
+    let front: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 0.0, 0.0), 1.0));
+    let back: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(0.0, 0.0, 1.0), 1.0));
+    // unit square in the xy plane, front faces +z
+    let card = Quad::new(
+        Point::new(-0.5, -0.5, 0.0),
+        Point::new(1.0, 0.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+        Arc::new(TwoSided::new(&front, &back)),
+    );
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    for (z, expected) in [
+        (1.0, ARgb::new(1.0, 0.0, 0.0)),
+        (-1.0, ARgb::new(0.0, 0.0, 1.0)),
+    ] {
+        let ray = Ray::new(Point::new(0.1, 0.2, z), Point::new(0.0, 0.0, -z), None);
+        let hr = card.hit(&ray, &ray_t).expect("ray goes through the card");
+        assert_approx_eq::assert_approx_eq!(hr.t, 1.0);
+        assert_approx_eq::assert_approx_eq!(hr.tx_coord.u, 0.6);
+        assert_approx_eq::assert_approx_eq!(hr.tx_coord.v, 0.7);
+        // normal faces the ray on both sides
+        assert!(hr.n.scalar_prod(&ray.dir()) < 0.0);
+        let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+        assert!(hr.mat.scatter(&ray, &mut attenuation, &mut scattered, &hr));
+        for (a, e) in attenuation.channels().into_iter().zip(expected.channels()) {
+            assert_approx_eq::assert_approx_eq!(a, e);
+        }
+    }
+    let outside = Ray::new(Point::new(0.6, 0.0, 1.0), Point::new(0.0, 0.0, -1.0), None);
+    assert!(card.hit(&outside, &ray_t).is_none());
+    assert!(!card.occluded(&outside, &ray_t));
+}
+
+#[test]
+fn test_quad_cutout_lets_rays_through_to_what_is_behind() {
+    use super::{
+        material::Lambertian,
+        texture::test_masks::{half_cutout, invisible_cutout},
+    };
+    use crate::core::rgb::ARgb;
+
+    // leaf card: only the left half along u is present
+    let mat: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let card = Quad::new(
+        Point::new(0.0, 0.0, 0.0),
+        Point::new(1.0, 0.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+        Arc::clone(&mat),
+    )
+    .with_opacity(half_cutout());
+    let backdrop = Quad::new(
+        Point::new(-1.0, -1.0, -1.0),
+        Point::new(3.0, 0.0, 0.0),
+        Point::new(0.0, 3.0, 0.0),
+        Arc::clone(&mat),
+    );
+    let mut scene = super::hittable::Scene::default();
+    scene.add(&(Arc::new(card) as Arc<dyn Hittable>));
+    scene.add(&(Arc::new(backdrop) as Arc<dyn Hittable>));
+    scene.build_bvh();
+
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    for (x, expected_t) in [(0.25, 1.0), (0.75, 2.0)] {
+        let ray = Ray::new(Point::new(x, 0.5, 1.0), Point::new(0.0, 0.0, -1.0), None);
+        let hr = scene.hit(&ray, &ray_t).expect("card or backdrop is hit");
+        assert_approx_eq::assert_approx_eq!(hr.t, expected_t);
+        // shadow rays stopping short of the backdrop only see the card
+        let to_backdrop = Interval::new(0.001, 1.5);
+        assert_eq!(scene.occluded(&ray, &to_backdrop), x < 0.5);
+    }
+
+    let invisible = Quad::new(
+        Point::new(0.0, 0.0, 0.0),
+        Point::new(1.0, 0.0, 0.0),
+        Point::new(0.0, 1.0, 0.0),
+        mat,
+    )
+    .with_opacity(invisible_cutout());
+    let ray = Ray::new(Point::new(0.25, 0.5, 1.0), Point::new(0.0, 0.0, -1.0), None);
+    assert!(invisible.hit(&ray, &ray_t).is_none());
+    assert!(!invisible.occluded(&ray, &ray_t));
+}
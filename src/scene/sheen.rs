@@ -0,0 +1,171 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::Rng;
+
+use crate::core::{onb::Onb, point3::Point, ray::Ray, rgb::ARgb};
+
+use super::{
+    hittable::HitRec,
+    material::Material,
+    microfacet::Charlie,
+    texture::{SolidColor, Texture},
+};
+
+// Cloth (velvet, satin, felt): charlie sheen of fibers sticking out of the surface,
+// bright at grazing angles, optionally over a base for the woven material underneath.
+// The base is not darkened by the sheen layer, so keep sheen colours moderate.
+pub struct Sheen {
+    color: Arc<dyn Texture>,
+    // scalar, read from luminance
+    roughness: Arc<dyn Texture>,
+    base: Option<Arc<dyn Material>>,
+}
+
+impl Sheen {
+    #[must_use]
+    pub fn new(color: ARgb, roughness: f64) -> Self {
+        let color: Arc<dyn Texture> = Arc::new(SolidColor::new(color));
+        let roughness: Arc<dyn Texture> =
+            Arc::new(SolidColor::new(ARgb::new(roughness, roughness, roughness)));
+        Self::with_textures(&color, &roughness)
+    }
+
+    pub fn with_textures(color: &Arc<dyn Texture>, roughness: &Arc<dyn Texture>) -> Self {
+        Self {
+            color: Arc::clone(color),
+            roughness: Arc::clone(roughness),
+            base: None,
+        }
+    }
+
+    // e.g. dark lambertian for velvet
+    #[must_use]
+    pub fn over(mut self, base: &Arc<dyn Material>) -> Self {
+        self.base = Some(Arc::clone(base));
+        self
+    }
+
+    fn lookup(&self, hr: &HitRec) -> (ARgb, Charlie) {
+        let (u, v, p) = (hr.tx_coord.u, hr.tx_coord.v, &hr.p);
+        let roughness = self.roughness.color(u, v, p).luminance();
+        (
+            self.color.color(u, v, p),
+            Charlie::from_roughness(roughness),
+        )
+    }
+
+    // probability of sampling the sheen instead of the base
+    fn sheen_probability(&self, color: ARgb) -> f64 {
+        match self.base {
+            Some(_) => {
+                let l = color.luminance().max(0.0);
+                l / (l + 1.0)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+impl Material for Sheen {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let (color, charlie) = self.lookup(hr);
+        let p = self.sheen_probability(color);
+        let mut rng = rand::rng();
+        if rng.random::<f64>() >= p {
+            let Some(ref base) = self.base else {
+                return false;
+            };
+            let scatters = base.scatter(r_in, attenuation, scattered, hr);
+            *attenuation = *attenuation / (1.0 - p);
+            return scatters;
+        }
+
+        // cosine sampling, the sheen lobe is too wide for anything better to pay off
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = Point::random_cosine_on_hemisphere(&mut rng, &Point::new(0.0, 0.0, 1.0));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return false;
+        }
+        *scattered = r_in.continued(hr.p, onb.local_to_world(&wi));
+        *attenuation = color * (charlie.eval(&wo, &wi) * PI / wi.z() / p);
+        true
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let base = match self.base {
+            Some(ref base) => base.eval(r_in, wi, hr)?,
+            None => ARgb::default(),
+        };
+        let (color, charlie) = self.lookup(hr);
+        let onb = Onb::from_w(&hr.n);
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        Some(base + color * charlie.eval(&wo, &wi))
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point) -> ARgb {
+        match self.base {
+            Some(ref base) => base.emitted(u, v, p),
+            None => ARgb::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn sheen_albedo(sheen: &Sheen, degrees: f64, count: u32) -> f64 {
+    let n = Point::new(0.0, 0.0, 1.0);
+    let hr = HitRec::new(
+        Point::default(),
+        n,
+        1.0,
+        Arc::new(Sheen::new(ARgb::default(), 0.5)),
+    );
+    let theta = degrees.to_radians();
+    let dir = Point::new(theta.sin(), 0.0, -theta.cos());
+    let r_in = Ray::new(-dir, dir, None);
+    let mut total = 0.0;
+    for _ in 0..count {
+        let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+        if sheen.scatter(&r_in, &mut attenuation, &mut scattered, &hr) {
+            total += attenuation.luminance();
+        }
+    }
+    total / f64::from(count)
+}
+
+#[test]
+fn test_sheen_white_furnace_over_black_stays_below_one() {
+    use super::material::Lambertian;
+
+    // black base absorbs everything, what comes back is the sheen alone
+    let black: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::default(), 1.0));
+    for roughness in [0.07, 0.1, 0.3, 0.6, 1.0] {
+        let sheen = Sheen::new(ARgb::new(1.0, 1.0, 1.0), roughness).over(&black);
+        for degrees in [0.0, 45.0, 75.0, 85.0, 89.5] {
+            let albedo = sheen_albedo(&sheen, degrees, 20_000);
+            assert!(
+                albedo <= 1.02,
+                "{albedo} at roughness {roughness}, {degrees} degrees"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_sheen_is_strongest_at_grazing_angles() {
+    for roughness in [0.3, 0.6, 1.0] {
+        let sheen = Sheen::new(ARgb::new(1.0, 1.0, 1.0), roughness);
+        let albedos = [0.0, 45.0, 80.0].map(|degrees| sheen_albedo(&sheen, degrees, 20_000));
+        assert!(
+            albedos[0] < albedos[1] && albedos[1] < albedos[2],
+            "{albedos:?} at roughness {roughness}"
+        );
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
 
 use super::{
     aabb::Aabb,
-    hittable::{HitRec, Hittable},
+    hittable::{HitRec, Hittable, Opacity},
     material::Material,
 };
 
@@ -16,6 +16,7 @@ pub struct Sphere {
     center: Center,
     mat: Arc<dyn Material>,
     bbox: Aabb,
+    opacity: Option<Opacity>,
 }
 
 pub enum Center {
@@ -32,6 +33,7 @@ impl Sphere {
             center: Center::Static(center),
             mat,
             bbox: Aabb::from_points(&(center - rvec), &(center + rvec)),
+            opacity: None,
         }
     }
     pub fn new(
@@ -51,6 +53,7 @@ impl Sphere {
                     center: Center::Moving(center, second_center),
                     mat,
                     bbox: Aabb::expand(&box1, &box2),
+                    opacity: None,
                 }
             }
 
@@ -58,6 +61,13 @@ impl Sphere {
         }
     }
 
+    // cut out parts of the sphere, rays go through to the far side or beyond
+    #[must_use]
+    pub fn with_opacity(mut self, opacity: Opacity) -> Self {
+        self.opacity = Some(opacity);
+        self
+    }
+
     fn center_at(&self, tm: f64) -> Point {
         match self.center {
             Center::Static(point) => point,
@@ -69,18 +79,10 @@ impl Sphere {
         }
     }
 
-    fn uv(p: &Point) -> (f64, f64) {
-        let theta = -p.y().acos();
-        let phi = -p.z().atan2(p.x()) + PI;
-        (0.5 * phi * FRAC_1_PI, theta * FRAC_1_PI)
-    }
-}
-
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<super::hittable::HitRec> {
-        let cur_center = self.center_at(ray.time());
+    // both t of ray-sphere intersection in increasing order, anywhere along the line
+    fn roots(&self, ray: &Ray, cur_center: &Point) -> Option<[f64; 2]> {
         // temp vec for sphere center - origin of ray
-        let oc = cur_center - ray.orig();
+        let oc = *cur_center - ray.orig();
         // quad equation
         // it's less computation if we assume b = -2h
         // thus h = (d, (C - Q)); t = (h - sqrt(d)/a);
@@ -89,27 +91,90 @@ impl Hittable for Sphere {
         let c = oc.scalar_prod(&oc) - self.r * self.r;
         let d = h * h - a * c;
         if d < 0.0 {
-            None
-        } else {
-            // we want smallest t, nearest to camera intersection
-            let sqrd = f64::sqrt(d);
-            let mut t = (h - sqrd) / a;
-            if !ray_t_possible.surrounds(t) {
-                t = (h + sqrd) / a;
-            }
+            return None;
+        }
+        let sqrd = f64::sqrt(d);
+        Some([(h - sqrd) / a, (h + sqrd) / a])
+    }
 
-            if ray_t_possible.surrounds(t) {
-                let p = ray.at(t);
-                let outward_normal = (p - cur_center) / self.r;
+    // smallest t of ray-sphere intersection inside ray_t_possible
+    fn nearest_root(
+        &self,
+        ray: &Ray,
+        cur_center: &Point,
+        ray_t_possible: &Interval,
+    ) -> Option<f64> {
+        // we want smallest t, nearest to camera intersection
+        self.roots(ray, cur_center)?
+            .into_iter()
+            .find(|t| ray_t_possible.surrounds(*t))
+    }
 
-                let mut hr = HitRec::new(p, outward_normal, t, Arc::clone(&self.mat));
-                hr.set_face_normal(ray, &outward_normal);
-                hr.set_uv(Sphere::uv(&outward_normal));
-                Some(hr)
-            } else {
-                None
-            }
+    // derivatives of the point along u and v of Sphere::uv at unit normal n,
+    // u goes around the y axis, v from the top pole
+    fn tangents(&self, n: &Point) -> (Point, Point) {
+        let dpdu = Point::new(n.z(), 0.0, -n.x()) * (2.0 * PI * self.r);
+        // cos and sin of the polar angle, dp/dv is undefined at the poles
+        let (cos_polar, sin_polar) = (n.y(), f64::hypot(n.x(), n.z()));
+        if sin_polar < 1e-9 {
+            return (dpdu, Point::default());
         }
+        let dpdv = Point::new(
+            cos_polar * n.x() / sin_polar,
+            -sin_polar,
+            cos_polar * n.z() / sin_polar,
+        ) * (PI * self.r);
+        (dpdu, dpdv)
+    }
+
+    // whether the surface is present at t, according to the opacity mask
+    fn kept(&self, ray: &Ray, cur_center: &Point, t: f64) -> bool {
+        self.opacity.as_ref().is_none_or(|opacity| {
+            let p = ray.at(t);
+            let (u, v) = Sphere::uv(&((p - *cur_center) / self.r));
+            opacity.keeps(u, v, &p)
+        })
+    }
+
+    // both in [0, 1], v is 0 at the top pole like the top row of an image
+    fn uv(p: &Point) -> (f64, f64) {
+        let theta = p.y().acos();
+        let phi = -p.z().atan2(p.x()) + PI;
+        (0.5 * phi * FRAC_1_PI, theta * FRAC_1_PI)
+    }
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<super::hittable::HitRec> {
+        let cur_center = self.center_at(ray.time());
+        // near side may be cut out, the far one is still visible through the hole
+        let t = self
+            .roots(ray, &cur_center)?
+            .into_iter()
+            .find(|t| ray_t_possible.surrounds(*t) && self.kept(ray, &cur_center, *t))?;
+        let p = ray.at(t);
+        let outward_normal = (p - cur_center) / self.r;
+
+        let mut hr = HitRec::new(p, outward_normal, t, Arc::clone(&self.mat));
+        hr.set_face_normal(ray, &outward_normal);
+        hr.set_uv(Sphere::uv(&outward_normal));
+        let (dpdu, dpdv) = self.tangents(&outward_normal);
+        hr.set_tangents(dpdu, dpdv);
+        Some(hr)
+    }
+
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        let cur_center = self.center_at(ray.time());
+        if self.opacity.is_none() {
+            return self
+                .nearest_root(ray, &cur_center, ray_t_possible)
+                .is_some();
+        }
+        self.roots(ray, &cur_center).is_some_and(|roots| {
+            roots
+                .into_iter()
+                .any(|t| ray_t_possible.surrounds(t) && self.kept(ray, &cur_center, t))
+        })
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -120,3 +185,46 @@ impl Hittable for Sphere {
 fn rvec(r: f64) -> Point {
     Point::new(r, r, r)
 }
+
+#[test]
+fn test_cutout_sphere_shows_far_side_through_hole() {
+    use super::{
+        material::Lambertian,
+        texture::test_masks::{half_cutout, invisible_cutout},
+    };
+    use crate::core::rgb::ARgb;
+
+    // half of the sphere with u above 0.5 is cut out
+    let mat: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let sphere =
+        Sphere::new_static(1.0, Point::default(), Arc::clone(&mat)).with_opacity(half_cutout());
+    let ray = Ray::new(Point::new(0.0, 0.0, -5.0), Point::new(0.0, 0.0, 1.0), None);
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let hr = sphere.hit(&ray, &ray_t).expect("far side is opaque");
+    assert_approx_eq::assert_approx_eq!(hr.t, 6.0);
+    assert!(sphere.occluded(&ray, &ray_t));
+
+    let invisible = Sphere::new_static(1.0, Point::default(), mat).with_opacity(invisible_cutout());
+    assert!(invisible.hit(&ray, &ray_t).is_none());
+    assert!(!invisible.occluded(&ray, &ray_t));
+}
+
+#[test]
+fn test_sphere_tangents_follow_uv() {
+    use super::material::Lambertian;
+    use crate::core::rgb::ARgb;
+
+    let mat: Arc<dyn Material> = Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0));
+    let sphere = Sphere::new_static(2.0, Point::default(), mat);
+    let n = Point::new(0.3, 0.5, -0.7).unit();
+    let (dpdu, dpdv) = sphere.tangents(&n);
+    let (u, v) = Sphere::uv(&n);
+    // small step along each derivative moves only its own coordinate
+    let step = 1e-6;
+    let (u1, v1) = Sphere::uv(&(n * 2.0 + dpdu * step).unit());
+    assert_approx_eq::assert_approx_eq!((u1 - u) / step, 1.0, 1e-3);
+    assert_approx_eq::assert_approx_eq!((v1 - v) / step, 0.0, 1e-3);
+    let (u2, v2) = Sphere::uv(&(n * 2.0 + dpdv * step).unit());
+    assert_approx_eq::assert_approx_eq!((u2 - u) / step, 0.0, 1e-3);
+    assert_approx_eq::assert_approx_eq!((v2 - v) / step, 1.0, 1e-3);
+}
@@ -0,0 +1,217 @@
+use std::{f64::consts::FRAC_1_PI, sync::Arc};
+
+use rand::Rng;
+
+use crate::{
+    core::{point3::Point, ray::Ray, rgb::ARgb},
+    utils::interval::Interval,
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRec, Hittable, NormalFace},
+    material::Material,
+    medium::{Interaction, Medium},
+    microfacet,
+    phase::IsotropicPhase,
+};
+
+// Translucent solid (skin, wax, marble, milk): dense scattering medium enclosed by
+// a boundary. Light enters, random walks through the medium and leaves wherever the walk
+// reaches the boundary again, which lights up thin parts from behind.
+// Boundary is expected to be closed, its own material is replaced by the interface.
+pub struct Subsurface {
+    boundary: Arc<dyn Hittable>,
+    medium: Medium,
+    interface: Arc<dyn Material>,
+}
+
+impl Subsurface {
+    // mean free path per channel in world units, albedo is the fraction of scattered
+    // (not absorbed) light at every event
+    pub fn new(boundary: Arc<dyn Hittable>, mean_free_path: ARgb, albedo: ARgb, ior: f64) -> Self {
+        let sigma_t = mean_free_path.map(f64::recip);
+        let sigma_s = sigma_t * albedo;
+        let [tr, tg, tb] = sigma_t.channels();
+        let [sr, sg, sb] = sigma_s.channels();
+        let sigma_a = ARgb::new(tr - sr, tg - sg, tb - sb);
+        let medium = Medium::new(sigma_a, sigma_s, Arc::new(IsotropicPhase));
+        Self::with_medium(boundary, medium, ior)
+    }
+
+    // e.g. forward scattering henyey-greenstein for skin
+    pub fn with_medium(boundary: Arc<dyn Hittable>, medium: Medium, ior: f64) -> Self {
+        Self {
+            boundary,
+            medium,
+            interface: Arc::new(Interface { ior }),
+        }
+    }
+
+    fn interface_hit(&self, mut hr: HitRec, tint: Option<ARgb>) -> HitRec {
+        // walks leave through the outside, which is where they are lit from
+        if matches!(hr.face, NormalFace::Inside) {
+            hr.n = -hr.n;
+        }
+        hr.mat = match tint {
+            Some(weight) => Arc::new(Tinted {
+                inner: Arc::clone(&self.interface),
+                weight,
+            }),
+            None => Arc::clone(&self.interface),
+        };
+        hr
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        // next boundary crossing may be behind something closer, it still tells
+        // whether the ray travels inside
+        let next = self
+            .boundary
+            .hit(ray, &Interval::new(ray_t_possible.min, f64::INFINITY))?;
+        if matches!(next.face, NormalFace::Outside) {
+            return (next.t <= ray_t_possible.max).then(|| self.interface_hit(next, None));
+        }
+
+        let t_start = f64::max(ray_t_possible.min, 0.0);
+        let t_end = f64::min(next.t, ray_t_possible.max);
+        let ray_length = ray.dir().size();
+        match self
+            .medium
+            .sample((t_end - t_start) * ray_length, &mut rand::rng())
+        {
+            Interaction::Scatter { distance, material } => {
+                let t = t_start + distance / ray_length;
+                // normal is arbitrary, phase functions don't use it
+                Some(HitRec::new(
+                    ray.at(t),
+                    Point::new(1.0, 0.0, 0.0),
+                    t,
+                    material,
+                ))
+            }
+            Interaction::Pass { tint } => {
+                (next.t <= ray_t_possible.max).then(|| self.interface_hit(next, tint))
+            }
+        }
+    }
+
+    // shadow rays from inside of the medium are blocked, light gets in by the walk
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        self.boundary.occluded(ray, ray_t_possible)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+// Boundary of the medium. Refraction is not traced, it is hidden by the many scattering
+// events anyway: light enters and leaves diffusely, only the specular reflection on the
+// way in depends on ior. Diffuse exits can be lit by light sampling, so walks don't have
+// to find small lights by chance.
+struct Interface {
+    ior: f64,
+}
+
+impl Material for Interface {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let mut rng = rand::rng();
+        // outside hits keep the normal facing the ray, inside ones face out
+        let dir = match hr.face {
+            NormalFace::Outside => {
+                let unit_dir = r_in.dir().unit();
+                let cos = -unit_dir.scalar_prod(&hr.n);
+                if rng.random::<f64>() < microfacet::fresnel_dielectric(cos, self.ior) {
+                    unit_dir.reflect(&hr.n)
+                } else {
+                    Point::random_cosine_on_hemisphere(&mut rng, &-hr.n)
+                }
+            }
+            NormalFace::Inside => Point::random_cosine_on_hemisphere(&mut rng, &hr.n),
+        };
+        *scattered = r_in.continued(hr.p, dir);
+        *attenuation = ARgb::new(1.0, 1.0, 1.0);
+        true
+    }
+
+    // specular reflection on the way in can't be evaluated, exits are lambertian
+    fn eval(&self, _r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        let NormalFace::Inside = hr.face else {
+            return None;
+        };
+        let cos = wi.scalar_prod(&hr.n);
+        (cos > 0.0).then(|| ARgb::new(1.0, 1.0, 1.0) * (cos * FRAC_1_PI))
+    }
+}
+
+// interface reached through a coloured medium, weighted by the spectral MIS correction
+struct Tinted {
+    inner: Arc<dyn Material>,
+    weight: ARgb,
+}
+
+impl Material for Tinted {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let scatters = self.inner.scatter(r_in, attenuation, scattered, hr);
+        *attenuation = *attenuation * self.weight;
+        scatters
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        self.inner.eval(r_in, wi, hr).map(|f| f * self.weight)
+    }
+}
+
+#[test]
+fn test_subsurface_walk_conserves_energy_and_transmits() {
+    use super::{material::Lambertian, sphere::Sphere};
+
+    // non-absorbing sphere a few mean free paths across, every walk has to leave it
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        1.0,
+        Point::default(),
+        Arc::new(Lambertian::new(ARgb::new(1.0, 1.0, 1.0), 1.0)),
+    ));
+    let white = ARgb::new(1.0, 1.0, 1.0);
+    let object = Subsurface::new(boundary, white * 0.5, white, 1.3);
+
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let walks = 5_000;
+    let (mut left, mut behind) = (0.0, 0.0);
+    for _ in 0..walks {
+        let mut ray = Ray::new(Point::new(0.0, 0.0, -3.0), Point::new(0.0, 0.0, 1.0), None);
+        let mut throughput = 1.0;
+        for _ in 0..10_000 {
+            let Some(hr) = object.hit(&ray, &ray_t) else {
+                left += throughput;
+                if ray.orig().z() > 0.0 && ray.dir().z() > 0.0 {
+                    behind += throughput;
+                }
+                break;
+            };
+            let (mut attenuation, mut scattered) = (ARgb::default(), Ray::default());
+            assert!(hr.mat.scatter(&ray, &mut attenuation, &mut scattered, &hr));
+            throughput *= attenuation.channels()[0];
+            ray = scattered;
+        }
+    }
+    let walks = f64::from(walks);
+    assert!((left / walks - 1.0).abs() < 0.02, "left {}", left / walks);
+    // some light goes through to the far side
+    assert!(behind / walks > 0.05, "behind {}", behind / walks);
+}
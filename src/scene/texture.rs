@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
-use image::RgbImage;
+use image::RgbaImage;
 
 use crate::core::{
     point3::Point,
     rgb::{ARgb, SOLID_CYAN_COLOR},
 };
 
+use super::noise::{self, Metric, Perlin, Worley};
+
 pub trait Texture: Send + Sync {
     fn color(&self, u: f64, v: f64, p: &Point) -> ARgb;
+    // opacity in [0, 1], used by cutout masks
+    fn alpha(&self, _u: f64, _v: f64, _p: &Point) -> f64 {
+        1.0
+    }
 }
 
 pub struct SolidColor {
@@ -16,6 +22,7 @@ pub struct SolidColor {
 }
 
 impl SolidColor {
+    #[must_use]
     pub fn new(albedo: ARgb) -> Self {
         Self { albedo }
     }
@@ -34,6 +41,7 @@ pub struct CheckerTexture {
 }
 
 impl CheckerTexture {
+    #[must_use]
     pub fn new(scale: f64, even_color: ARgb, odd_color: ARgb) -> Self {
         Self {
             inv_scale: scale.recip(),
@@ -44,6 +52,7 @@ impl CheckerTexture {
 }
 
 impl Texture for CheckerTexture {
+    #[allow(clippy::cast_possible_truncation)]
     fn color(&self, u: f64, v: f64, p: &Point) -> ARgb {
         let is_even =
             p.e.iter()
@@ -58,37 +67,427 @@ impl Texture for CheckerTexture {
     }
 }
 
+// how texels are blended between pixel centres
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+    // catmull-rom over 4x4 texels, sharper than bilinear
+    Bicubic,
+}
+
+// what is read outside of [0, 1]
+#[derive(Clone, Copy, Debug)]
+pub enum Wrap {
+    // tiles
+    Repeat,
+    // tiles flipping every other copy, no seams
+    Mirror,
+    // edge texels extend forever
+    Clamp,
+}
+
+impl Wrap {
+    // texel index inside of 0..n
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn index(self, i: i64, n: u32) -> u32 {
+        let n = i64::from(n);
+        (match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n {
+                    m
+                } else {
+                    2 * n - 1 - m
+                }
+            }
+            Wrap::Clamp => i.clamp(0, n - 1),
+        }) as u32
+    }
+}
+
+// Image mapped over [0, 1] texture coordinates, v = 0 is the top row. Texel centres are
+// at half-integer positions, e.g. u = 0.5 / width is the middle of the first column.
 pub struct ImageTexture {
-    px_colors: Arc<RgbImage>,
+    px_colors: Arc<RgbaImage>,
+    filter: Filter,
+    wrap_u: Wrap,
+    wrap_v: Wrap,
 }
 
 impl ImageTexture {
-    pub fn new(px_colors: Arc<RgbImage>) -> Self {
-        Self { px_colors }
+    #[must_use]
+    pub fn new(px_colors: Arc<RgbaImage>) -> Self {
+        Self {
+            px_colors,
+            filter: Filter::Nearest,
+            wrap_u: Wrap::Clamp,
+            wrap_v: Wrap::Clamp,
+        }
+    }
+
+    #[must_use]
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    #[must_use]
+    pub fn with_wrap(mut self, wrap_u: Wrap, wrap_v: Wrap) -> Self {
+        self.wrap_u = wrap_u;
+        self.wrap_v = wrap_v;
+        self
+    }
+
+    // rgba in [0, 1] of texel (i, j), wrapped into the image
+    fn texel(&self, i: i64, j: i64) -> [f64; 4] {
+        let x = self.wrap_u.index(i, self.px_colors.width());
+        let y = self.wrap_v.index(j, self.px_colors.height());
+        self.px_colors
+            .get_pixel(x, y)
+            .0
+            .map(|c| f64::from(c) / 255.0)
+    }
+
+    // filtered rgba, None for empty images
+    #[allow(clippy::cast_possible_truncation)]
+    fn sample(&self, u: f64, v: f64) -> Option<[f64; 4]> {
+        let (width, height) = self.px_colors.dimensions();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        // continuous texel coordinates, integers at texel centres
+        let x = u * f64::from(width) - 0.5;
+        let y = v * f64::from(height) - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (i, j) = (x0 as i64, y0 as i64);
+
+        let rgba = match self.filter {
+            // texel containing the point, floor(x + 0.5) would round half texels down
+            // at negative coordinates
+            Filter::Nearest => self.texel(
+                (u * f64::from(width)).floor() as i64,
+                (v * f64::from(height)).floor() as i64,
+            ),
+            Filter::Bilinear => {
+                let weights = |f: f64| [(0, 1.0 - f), (1, f)];
+                self.weighted(i, j, &weights(fx), &weights(fy))
+            }
+            Filter::Bicubic => {
+                let weights = |f: f64| {
+                    let [w0, w1, w2, w3] = catmull_rom(f);
+                    [(-1, w0), (0, w1), (1, w2), (2, w3)]
+                };
+                // catmull-rom overshoots next to sharp edges
+                self.weighted(i, j, &weights(fx), &weights(fy))
+                    .map(|c| c.clamp(0.0, 1.0))
+            }
+        };
+        Some(rgba)
+    }
+
+    // separable filter, offsets from texel (i, j) and their weights along each axis
+    fn weighted(&self, i: i64, j: i64, wx: &[(i64, f64)], wy: &[(i64, f64)]) -> [f64; 4] {
+        let mut sum = [0.0; 4];
+        for &(dj, w_row) in wy {
+            for &(di, w_col) in wx {
+                let texel = self.texel(i + di, j + dj);
+                for (s, t) in sum.iter_mut().zip(texel) {
+                    *s += w_row * w_col * t;
+                }
+            }
+        }
+        sum
     }
 }
 
+// weights of the 4 texels around a fraction f in [0, 1) between the middle two
+fn catmull_rom(f: f64) -> [f64; 4] {
+    let (f2, f3) = (f * f, f * f * f);
+    [
+        0.5 * (-f3 + 2.0 * f2 - f),
+        0.5 * (3.0 * f3 - 5.0 * f2 + 2.0),
+        0.5 * (-3.0 * f3 + 4.0 * f2 + f),
+        0.5 * (f3 - f2),
+    ]
+}
+
 impl Texture for ImageTexture {
-    // this may not happen, in case of [0,1] f64
-    #![allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
     fn color(&self, u: f64, v: f64, _p: &Point) -> ARgb {
-        let width = self.px_colors.width();
-        let height = self.px_colors.height();
-
-        if height == 0 {
+        let Some([r, g, b, _]) = self.sample(u, v) else {
             return SOLID_CYAN_COLOR;
+        };
+        ARgb::new(r, g, b)
+    }
+
+    fn alpha(&self, u: f64, v: f64, _p: &Point) -> f64 {
+        self.sample(u, v).map_or(1.0, |[_, _, _, a]| a)
+    }
+}
+
+fn lerp(a: ARgb, b: ARgb, t: f64) -> ARgb {
+    a * (1.0 - t) + b * t
+}
+
+// Grey perlin noise over the 3d point, scale is its frequency in world units
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    #[must_use]
+    pub fn new(seed: u64, scale: f64) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
         }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn color(&self, _u: f64, _v: f64, p: &Point) -> ARgb {
+        let n = 0.5 * (1.0 + self.noise.noise(&(*p * self.scale)));
+        ARgb::new(n, n, n)
+    }
+}
 
-        let u = u.clamp(0.0, 1.0);
-        let v = v.abs().clamp(0.0, 1.0);
+// Veins along z distorted by turbulence, sine of the phase blends base and vein colours
+pub struct MarbleTexture {
+    noise: Perlin,
+    scale: f64,
+    // phase shift of the strongest turbulence, and its number of octaves
+    turbulence: f64,
+    octaves: u32,
+    base: ARgb,
+    vein: ARgb,
+}
 
-        let i = ((u * f64::from(width)) as u32).min(width - 1);
-        let j = ((v * f64::from(height)) as u32).min(height - 1);
-        let px = self.px_colors.get_pixel(i, j).0;
-        let r = f64::from(px[0]) / 255.0;
-        let g = f64::from(px[1]) / 255.0;
-        let b = f64::from(px[2]) / 255.0;
+impl MarbleTexture {
+    #[must_use]
+    pub fn new(seed: u64, scale: f64, base: ARgb, vein: ARgb) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            turbulence: 10.0,
+            octaves: 7,
+            base,
+            vein,
+        }
+    }
 
-        ARgb::new(r, g, b)
+    // calmer (or wilder) veins
+    #[must_use]
+    pub fn with_turbulence(mut self, turbulence: f64, octaves: u32) -> Self {
+        self.turbulence = turbulence;
+        self.octaves = octaves;
+        self
     }
 }
+
+impl Texture for MarbleTexture {
+    fn color(&self, _u: f64, _v: f64, p: &Point) -> ARgb {
+        let p = *p * self.scale;
+        let phase = p.z() + self.turbulence * self.noise.turbulence(&p, self.octaves);
+        lerp(self.vein, self.base, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// Growth rings around the y axis, wobbled by noise. Scale is the number of rings per
+// world unit.
+pub struct WoodTexture {
+    noise: Perlin,
+    scale: f64,
+    light: ARgb,
+    dark: ARgb,
+}
+
+impl WoodTexture {
+    #[must_use]
+    pub fn new(seed: u64, scale: f64, light: ARgb, dark: ARgb) -> Self {
+        Self {
+            noise: Perlin::new(seed),
+            scale,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn color(&self, _u: f64, _v: f64, p: &Point) -> ARgb {
+        let p = *p * self.scale;
+        let radius = f64::hypot(p.x(), p.z()) + 0.4 * self.noise.noise(&(p * 0.5));
+        let ring = radius - radius.floor();
+        // late wood is a narrow dark band at the end of every ring
+        lerp(self.light, self.dark, ring.powi(4))
+    }
+}
+
+// what a voronoi texture shows
+#[derive(Clone, Copy, Debug)]
+pub enum VoronoiOutput {
+    // distance to the nearest feature point, grey
+    F1,
+    // distance to the second nearest one
+    F2,
+    // zero on cell borders, cracks and mortar lines
+    F2MinusF1,
+    // random colour per cell
+    CellColor,
+}
+
+// Cellular patterns (stone, scales, cracked mud) from worley noise over the 3d point,
+// scale is the number of cells per world unit. Distances are in cell units, shown
+// clamped to [0, 1].
+pub struct VoronoiTexture {
+    worley: Worley,
+    scale: f64,
+    output: VoronoiOutput,
+}
+
+impl VoronoiTexture {
+    #[must_use]
+    pub fn new(seed: u64, scale: f64, metric: Metric, output: VoronoiOutput) -> Self {
+        Self {
+            worley: Worley::new(seed, metric),
+            scale,
+            output,
+        }
+    }
+}
+
+impl Texture for VoronoiTexture {
+    fn color(&self, _u: f64, _v: f64, p: &Point) -> ARgb {
+        let sample = self.worley.sample(&(*p * self.scale));
+        let value = match self.output {
+            VoronoiOutput::F1 => sample.f1,
+            VoronoiOutput::F2 => sample.f2,
+            VoronoiOutput::F2MinusF1 => sample.f2 - sample.f1,
+            VoronoiOutput::CellColor => {
+                let [r, g, b] = noise::unit_floats(sample.cell);
+                return ARgb::new(r, g, b);
+            }
+        };
+        let value = value.clamp(0.0, 1.0);
+        ARgb::new(value, value, value)
+    }
+}
+
+// masks shared by the cutout tests of the shapes
+#[cfg(test)]
+pub mod test_masks {
+    use std::sync::Arc;
+
+    use crate::{
+        core::{point3::Point, rgb::ARgb},
+        scene::hittable::{AlphaMode, Opacity},
+    };
+
+    use super::{SolidColor, Texture};
+
+    // opaque up to u = 0.5, cut out above
+    pub struct HalfMask;
+
+    impl Texture for HalfMask {
+        fn color(&self, _u: f64, _v: f64, _p: &Point) -> ARgb {
+            ARgb::default()
+        }
+        fn alpha(&self, u: f64, _v: f64, _p: &Point) -> f64 {
+            if u > 0.5 {
+                0.0
+            } else {
+                1.0
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn half_cutout() -> Opacity {
+        let half: Arc<dyn Texture> = Arc::new(HalfMask);
+        Opacity::new(&half, AlphaMode::Cutout(0.5))
+    }
+
+    // solid colours are opaque, alpha of a cutout has to reach the threshold,
+    // so a threshold above one removes the whole surface
+    #[must_use]
+    pub fn invisible_cutout() -> Opacity {
+        let solid: Arc<dyn Texture> = Arc::new(SolidColor::new(ARgb::default()));
+        Opacity::new(&solid, AlphaMode::Cutout(1.1))
+    }
+}
+
+// grey image from a row-major list of values, alpha is opaque
+#[cfg(test)]
+fn grey_image(width: u32, values: &[u8]) -> Arc<RgbaImage> {
+    let height = u32::try_from(values.len()).expect("small test image") / width;
+    Arc::new(RgbaImage::from_fn(width, height, |x, y| {
+        let g = values[(y * width + x) as usize];
+        image::Rgba([g, g, g, 255])
+    }))
+}
+
+#[test]
+fn test_image_filters_sample_texel_centres() {
+    let p = Point::default();
+    let red = |t: &ImageTexture, u: f64, v: f64| t.color(u, v, &p).channels()[0];
+    // black and white column
+    let pair = grey_image(2, &[0, 255]);
+
+    let nearest = ImageTexture::new(Arc::clone(&pair));
+    assert_approx_eq::assert_approx_eq!(red(&nearest, 0.0, 0.5), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&nearest, 0.49, 0.5), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&nearest, 0.5, 0.5), 1.0);
+    assert_approx_eq::assert_approx_eq!(red(&nearest, 1.0, 0.5), 1.0);
+
+    // exact at texel centres, linear between them, flat beyond them when clamped
+    let bilinear = ImageTexture::new(Arc::clone(&pair)).with_filter(Filter::Bilinear);
+    assert_approx_eq::assert_approx_eq!(red(&bilinear, 0.25, 0.5), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&bilinear, 0.75, 0.5), 1.0);
+    assert_approx_eq::assert_approx_eq!(red(&bilinear, 0.5, 0.5), 0.5);
+    assert_approx_eq::assert_approx_eq!(red(&bilinear, 0.375, 0.5), 0.25);
+    assert_approx_eq::assert_approx_eq!(red(&bilinear, 0.1, 0.5), 0.0);
+
+    // catmull-rom reproduces a linear ramp between its texels
+    let ramp = grey_image(4, &[0, 85, 170, 255]);
+    let bicubic = ImageTexture::new(ramp).with_filter(Filter::Bicubic);
+    assert_approx_eq::assert_approx_eq!(red(&bicubic, 0.375, 0.5), 1.0 / 3.0);
+    assert_approx_eq::assert_approx_eq!(red(&bicubic, 0.5, 0.5), 0.5);
+    assert_approx_eq::assert_approx_eq!(red(&bicubic, 0.5625, 0.5), 7.0 / 12.0);
+}
+
+#[test]
+fn test_image_wrap_modes_per_axis() {
+    let p = Point::default();
+    let red = |t: &ImageTexture, u: f64, v: f64| t.color(u, v, &p).channels()[0];
+    // 2x2: black, white on top; white, black at the bottom
+    let checker = grey_image(2, &[0, 255, 255, 0]);
+    let texture = |wrap_u, wrap_v| {
+        ImageTexture::new(Arc::clone(&checker))
+            .with_filter(Filter::Bilinear)
+            .with_wrap(wrap_u, wrap_v)
+    };
+
+    // on the left edge of the first row: blend with the last column only when repeating
+    let repeat = texture(Wrap::Repeat, Wrap::Clamp);
+    assert_approx_eq::assert_approx_eq!(red(&repeat, 0.0, 0.25), 0.5);
+    assert_approx_eq::assert_approx_eq!(red(&repeat, 1.25, 0.25), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&repeat, -0.25, 0.25), 1.0);
+    let clamp = texture(Wrap::Clamp, Wrap::Clamp);
+    assert_approx_eq::assert_approx_eq!(red(&clamp, 0.0, 0.25), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&clamp, 7.0, 0.25), 1.0);
+    // mirrored copies meet with the same texel, next copy is flipped
+    let mirror = texture(Wrap::Mirror, Wrap::Clamp);
+    assert_approx_eq::assert_approx_eq!(red(&mirror, 0.0, 0.25), 0.0);
+    assert_approx_eq::assert_approx_eq!(red(&mirror, 1.25, 0.25), 1.0);
+    assert_approx_eq::assert_approx_eq!(red(&mirror, -0.25, 0.25), 0.0);
+
+    // v axis is independent: repeating rows blend top and bottom at v = 0
+    let rows = texture(Wrap::Clamp, Wrap::Repeat);
+    assert_approx_eq::assert_approx_eq!(red(&rows, 0.25, 0.0), 0.5);
+    assert_approx_eq::assert_approx_eq!(red(&rows, 0.25, 1.75), 1.0);
+    let clamped_rows = texture(Wrap::Repeat, Wrap::Clamp);
+    assert_approx_eq::assert_approx_eq!(red(&clamped_rows, 0.25, -3.0), 0.0);
+}
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::core::{
+    point3::Point,
+    ray::{MediumEntry, Ray},
+    rgb::ARgb,
+};
+
+use super::{
+    hittable::HitRec,
+    material::{ConductorPreset, Crossing, Material},
+    microfacet::{self, TrowbridgeReitz},
+    texture::{SolidColor, Texture},
+};
+
+// what is under the film
+#[derive(Clone, Copy, Debug)]
+pub enum ThinFilmBase {
+    // glass or liquid of given ior, 1.0 for soap bubbles (air inside)
+    Dielectric(f64),
+    // complex ior of a metal
+    Conductor(ARgb, ARgb),
+}
+
+impl From<ConductorPreset> for ThinFilmBase {
+    fn from(preset: ConductorPreset) -> Self {
+        let (eta, k) = preset.eta_k();
+        ThinFilmBase::Conductor(eta, k)
+    }
+}
+
+// Iridescent surface: thin film (soap, oil, anodized oxide) over a dielectric or metal base.
+// Interference inside of the film changes fresnel reflectance per channel with the viewing
+// angle and film thickness. The film itself doesn't absorb, so dielectric bases transmit
+// whatever is not reflected, in the direction given by the base alone.
+pub struct ThinFilm {
+    base: ThinFilmBase,
+    film_ior: f64,
+    // luminance of the texture times scale is thickness in nm
+    thickness: Arc<dyn Texture>,
+    thickness_scale: f64,
+    distribution: TrowbridgeReitz,
+}
+
+impl ThinFilm {
+    #[must_use]
+    pub fn new(base: ThinFilmBase, film_ior: f64, thickness: f64, roughness: f64) -> Self {
+        Self {
+            base,
+            film_ior,
+            thickness: Arc::new(SolidColor::new(ARgb::new(1.0, 1.0, 1.0))),
+            thickness_scale: thickness,
+            distribution: TrowbridgeReitz::from_roughness(roughness, 0.0),
+        }
+    }
+
+    // e.g. noise texture for swirls of an oil slick, white is scale nm thick
+    #[must_use]
+    pub fn with_thickness_texture(mut self, thickness: &Arc<dyn Texture>, scale: f64) -> Self {
+        self.thickness = Arc::clone(thickness);
+        self.thickness_scale = scale;
+        self
+    }
+
+    fn entry(&self, ior: f64) -> MediumEntry {
+        MediumEntry {
+            id: std::ptr::from_ref(self).addr(),
+            priority: 0,
+            ior,
+            absorption: None,
+        }
+    }
+
+    // ior on both sides of the film, None if the dielectric surface is not an interface
+    // (crossing tells where the ray goes then)
+    fn sides(&self, r_in: &Ray, hr: &HitRec) -> (Option<(f64, ARgb, ARgb)>, Option<Crossing>) {
+        match self.base {
+            ThinFilmBase::Conductor(eta, k) => (Some((1.0, eta, k)), None),
+            ThinFilmBase::Dielectric(ior) => {
+                let crossing = Crossing::resolve(self.entry(ior), r_in, hr);
+                let sides = crossing
+                    .etas
+                    .map(|(eta_i, eta_t)| (eta_i, ARgb::new(eta_t, eta_t, eta_t), ARgb::default()));
+                (sides, Some(crossing))
+            }
+        }
+    }
+
+    fn fresnel(&self, hr: &HitRec, cos: f64, sides: &(f64, ARgb, ARgb)) -> ARgb {
+        let thickness = self
+            .thickness
+            .color(hr.tx_coord.u, hr.tx_coord.v, &hr.p)
+            .luminance()
+            * self.thickness_scale;
+        let (eta_i, ref eta, ref k) = *sides;
+        microfacet::fresnel_thin_film(cos, eta_i, self.film_ior, thickness, eta, k)
+    }
+}
+
+impl Material for ThinFilm {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        let (sides, crossing) = self.sides(r_in, hr);
+        *attenuation = crossing
+            .as_ref()
+            .map_or(ARgb::new(1.0, 1.0, 1.0), |c| c.attenuation);
+        let Some(sides) = sides else {
+            // inside of a higher priority volume, this surface is not an interface
+            let next = crossing.map(|c| c.next).unwrap_or_default();
+            *scattered = r_in.continued(hr.p, r_in.dir()).with_media(&next);
+            return true;
+        };
+
+        let onb = hr.shading_frame();
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        if wo.z() <= 0.0 {
+            return false;
+        }
+        let mut rng = rand::rng();
+        let smooth = self.distribution.effectively_smooth();
+        let wm = if smooth {
+            Point::new(0.0, 0.0, 1.0)
+        } else {
+            self.distribution.sample_wm(&wo, &mut rng)
+        };
+        let f = self.fresnel(hr, wo.scalar_prod(&wm), &sides);
+        let reflected = microfacet::reflect(&wo, &wm);
+
+        let (wi, weight, next) = match crossing {
+            None => (reflected, f, None),
+            // dielectrics reflect with probability of the mean reflectance, colour of
+            // both reflection and transmission goes into the weight
+            Some(crossing) => {
+                let [r, g, b] = f.channels();
+                let reflect = (r + g + b) / 3.0;
+                let eta = crossing.etas.map_or(1.0, |(eta_i, eta_t)| eta_t / eta_i);
+                match microfacet::refract(&wo, &wm, eta).filter(|_| rng.random::<f64>() >= reflect)
+                {
+                    Some(wi) => (
+                        wi,
+                        f.map(|f| (1.0 - f) / (1.0 - reflect)),
+                        Some(crossing.next),
+                    ),
+                    None => (reflected, f / reflect, None),
+                }
+            }
+        };
+        // reflection has to stay above the surface, transmission below
+        if (wi.z() > 0.0) == next.is_some() {
+            return false;
+        }
+
+        let masking = if smooth {
+            1.0
+        } else {
+            self.distribution.g(&wo, &wi) / self.distribution.g1(&wo)
+        };
+        *attenuation = *attenuation * weight * masking;
+        let ray = r_in.continued(hr.p, onb.local_to_world(&wi));
+        *scattered = match next {
+            Some(next) => ray.with_media(&next),
+            None => ray,
+        };
+        true
+    }
+
+    // reflection only, transmitted light is left to scattered rays as with RoughDielectric
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        if self.distribution.effectively_smooth() {
+            return None;
+        }
+        let (sides, crossing) = self.sides(r_in, hr);
+        let sides = sides?;
+        let onb = hr.shading_frame();
+        let wo = onb.world_to_local(&-r_in.dir().unit());
+        let wi = onb.world_to_local(wi);
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let wm = (wo + wi).unit();
+        let f = self.fresnel(hr, wo.scalar_prod(&wm), &sides);
+        let d = &self.distribution;
+        let attenuation = crossing.map_or(ARgb::new(1.0, 1.0, 1.0), |c| c.attenuation);
+        Some(attenuation * f * (d.d(&wm) * d.g(&wo, &wi) / (4.0 * wo.z())))
+    }
+}
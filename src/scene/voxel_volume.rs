@@ -0,0 +1,475 @@
+use std::{fs, io, path::Path, sync::Arc};
+
+use rand::Rng;
+
+use crate::{
+    core::{point3::Point, ray::Ray, rgb::ARgb},
+    utils::interval::Interval,
+};
+
+use super::{
+    aabb::Aabb,
+    hittable::{HitRec, Hittable},
+    material::Material,
+};
+
+const BINARY_MAGIC: &[u8] = b"DGRD";
+
+#[derive(Debug)]
+pub enum GridError {
+    Read(io::Error),
+    // line number and what was wrong
+    Parse(usize, String),
+    SizeMismatch { expected: usize, found: usize },
+    // index of a negative, infinite or nan value
+    InvalidValue(usize),
+}
+
+// Regular grid of non-negative scalars (density, temperature) over the unit cube.
+//
+// Text format, whitespace separated, lines starting with '#' are comments:
+//   nx ny nz
+//   v(0,0,0) v(1,0,0) ... v(nx-1,0,0) v(0,1,0) ... v(nx-1,ny-1,nz-1)
+// i.e. x changes fastest, then y, then z. Values may span any number of lines.
+//
+// Binary format for baked grids, little endian:
+//   b"DGRD" nx ny nz (u32 each) followed by nx * ny * nz f32 values in the same order
+//
+// Samples are located at voxel centers.
+pub struct DensityGrid {
+    n: [usize; 3],
+    values: Vec<f64>,
+    max: f64,
+}
+
+impl DensityGrid {
+    #[allow(clippy::missing_errors_doc)]
+    pub fn new(n: [usize; 3], values: Vec<f64>) -> Result<Self, GridError> {
+        let expected = n[0] * n[1] * n[2];
+        if expected == 0 || values.len() != expected {
+            return Err(GridError::SizeMismatch {
+                expected,
+                found: values.len(),
+            });
+        }
+        // an infinite majorant would stall delta tracking
+        if let Some(index) = values.iter().position(|v| !(v.is_finite() && *v >= 0.0)) {
+            return Err(GridError::InvalidValue(index));
+        }
+        let max = values.iter().copied().fold(0.0, f64::max);
+        Ok(Self { n, values, max })
+    }
+
+    // either format, binary grids are recognized by their magic
+    #[allow(clippy::missing_errors_doc)]
+    pub fn load<T: AsRef<Path>>(filename: T) -> Result<Self, GridError> {
+        let bytes = fs::read(filename).map_err(GridError::Read)?;
+        if bytes.starts_with(BINARY_MAGIC) {
+            return Self::parse_bytes(&bytes);
+        }
+        let text = String::from_utf8(bytes)
+            .map_err(|e| GridError::Parse(0, format!("neither binary grid nor text: {e}")))?;
+        Self::parse(&text)
+    }
+
+    #[allow(clippy::missing_errors_doc)]
+    pub fn parse_bytes(bytes: &[u8]) -> Result<Self, GridError> {
+        let body = bytes
+            .strip_prefix(BINARY_MAGIC)
+            .ok_or_else(|| GridError::Parse(0, "missing binary grid magic".to_owned()))?;
+        let (header, data) = body
+            .split_at_checked(12)
+            .ok_or_else(|| GridError::Parse(0, "missing grid resolution".to_owned()))?;
+
+        let mut n = [0; 3];
+        for (dim, word) in n.iter_mut().zip(header.chunks_exact(4)) {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            *dim = usize::try_from(word)
+                .map_err(|e| GridError::Parse(0, format!("resolution {word}: {e}")))?;
+        }
+        // a trailing partial value is as wrong as a missing one
+        if data.len() % 4 != 0 {
+            return Err(GridError::SizeMismatch {
+                expected: n[0] * n[1] * n[2],
+                found: data.len() / 4,
+            });
+        }
+        let values = data
+            .chunks_exact(4)
+            .map(|v| f64::from(f32::from_le_bytes([v[0], v[1], v[2], v[3]])))
+            .collect();
+        Self::new(n, values)
+    }
+
+    #[allow(clippy::missing_errors_doc)]
+    pub fn parse(text: &str) -> Result<Self, GridError> {
+        let mut tokens = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim_start().starts_with('#'))
+            .flat_map(|(i, line)| line.split_whitespace().map(move |tok| (i + 1, tok)));
+
+        let mut n = [0; 3];
+        for dim in &mut n {
+            let (line, tok) = tokens
+                .next()
+                .ok_or_else(|| GridError::Parse(0, "missing grid resolution".to_owned()))?;
+            *dim = tok
+                .parse()
+                .map_err(|e| GridError::Parse(line, format!("resolution {tok}: {e}")))?;
+        }
+
+        let values = tokens
+            .map(|(line, tok)| match tok.parse::<f64>() {
+                Ok(v) if v.is_finite() && v >= 0.0 => Ok(v),
+                Ok(_) => Err(GridError::Parse(
+                    line,
+                    format!("value {tok} is not finite and non-negative"),
+                )),
+                Err(e) => Err(GridError::Parse(line, format!("value {tok}: {e}"))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(n, values)
+    }
+
+    #[must_use]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.values[(z * self.n[1] + y) * self.n[0] + x]
+    }
+
+    // trilinear interpolation, local coordinates are in [0, 1]^3 over the grid,
+    // outside of the grid samples are clamped to the border voxels
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    #[must_use]
+    pub fn lookup(&self, local: &Point) -> f64 {
+        let mut i0 = [0; 3];
+        let mut i1 = [0; 3];
+        let mut frac = [0.0; 3];
+        for axis in 0..3 {
+            let last = self.n[axis] - 1;
+            // continuous coordinate where voxel centers are integers
+            let c = (local.e[axis] * self.n[axis] as f64 - 0.5).clamp(0.0, last as f64);
+            let base = (c.floor() as usize).min(last);
+            i0[axis] = base;
+            i1[axis] = (base + 1).min(last);
+            frac[axis] = c - base as f64;
+        }
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let [fx, fy, fz] = frac;
+        let c00 = lerp(
+            self.at(i0[0], i0[1], i0[2]),
+            self.at(i1[0], i0[1], i0[2]),
+            fx,
+        );
+        let c10 = lerp(
+            self.at(i0[0], i1[1], i0[2]),
+            self.at(i1[0], i1[1], i0[2]),
+            fx,
+        );
+        let c01 = lerp(
+            self.at(i0[0], i0[1], i1[2]),
+            self.at(i1[0], i0[1], i1[2]),
+            fx,
+        );
+        let c11 = lerp(
+            self.at(i0[0], i1[1], i1[2]),
+            self.at(i1[0], i1[1], i1[2]),
+            fx,
+        );
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+// maps world point into the grid unit cube
+fn to_grid(bbox: &Aabb, p: &Point) -> Point {
+    Point::new(
+        (p.x() - bbox.x.min) / bbox.x.size(),
+        (p.y() - bbox.y.min) / bbox.y.size(),
+        (p.z() - bbox.z.min) / bbox.z.size(),
+    )
+}
+
+// material at real collisions inside of a voxel volume: scatters with the phase material,
+// emits according to the optional emission (temperature) grid.
+// Emission grid times colour is the source term per unit of extinction, sigma_a * Le /
+// sigma_t, which is what a collision of delta tracking adds. A thick volume glows with
+// that radiance whatever its density or grid resolution, a thin one with 1 - exp(-tau)
+// of it; for Le of the emission-absorption model scale it by the absorbed fraction.
+struct VoxelMedium {
+    phase: Arc<dyn Material>,
+    emission: Option<(Arc<DensityGrid>, ARgb)>,
+    bbox: Aabb,
+}
+
+impl Material for VoxelMedium {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        attenuation: &mut ARgb,
+        scattered: &mut Ray,
+        hr: &HitRec,
+    ) -> bool {
+        self.phase.scatter(r_in, attenuation, scattered, hr)
+    }
+
+    fn eval(&self, r_in: &Ray, wi: &Point, hr: &HitRec) -> Option<ARgb> {
+        self.phase.eval(r_in, wi, hr)
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, p: &Point) -> ARgb {
+        match self.emission {
+            Some((ref grid, color)) => color * grid.lookup(&to_grid(&self.bbox, p)),
+            None => ARgb::default(),
+        }
+    }
+
+    fn is_volumetric(&self) -> bool {
+        true
+    }
+}
+
+// Heterogeneous participating medium with density from a voxel grid stretched over bbox.
+// Free flights are sampled with delta tracking against the grid maximum (majorant),
+// which is unbiased for any density field.
+pub struct VoxelVolume {
+    bbox: Aabb,
+    density: Arc<DensityGrid>,
+    density_scale: f64,
+    // majorant: upper bound of scaled density over the whole volume
+    sigma_max: f64,
+    medium: Arc<dyn Material>,
+}
+
+impl VoxelVolume {
+    pub fn new(
+        bbox: Aabb,
+        density: Arc<DensityGrid>,
+        density_scale: f64,
+        phase: Arc<dyn Material>,
+        emission: Option<(Arc<DensityGrid>, ARgb)>,
+    ) -> Self {
+        let sigma_max = density.max() * density_scale;
+        Self {
+            bbox,
+            density,
+            density_scale,
+            sigma_max,
+            medium: Arc::new(VoxelMedium {
+                phase,
+                emission,
+                bbox,
+            }),
+        }
+    }
+
+    fn sigma(&self, p: &Point) -> f64 {
+        self.density.lookup(&to_grid(&self.bbox, p)) * self.density_scale
+    }
+
+    // tentative collisions against the majorant along ray inside of ray_t,
+    // on_collision decides whether tracking stops at t (returns true)
+    fn track(
+        &self,
+        ray: &Ray,
+        ray_t: &Interval,
+        rng: &mut impl Rng,
+        mut on_collision: impl FnMut(f64, &mut dyn rand::RngCore) -> bool,
+    ) -> Option<f64> {
+        if self.sigma_max <= 0.0 {
+            return None;
+        }
+        let inside = self.bbox.hit_interval(ray, ray_t)?;
+        let inv_step = (self.sigma_max * ray.dir().size()).recip();
+        let mut t = f64::max(inside.min, 0.0);
+        loop {
+            t -= f64::ln(1.0 - rng.random::<f64>()) * inv_step;
+            if t >= inside.max {
+                return None;
+            }
+            if on_collision(t, rng) {
+                return Some(t);
+            }
+        }
+    }
+}
+
+impl Hittable for VoxelVolume {
+    // delta tracking: tentative collision is real with probability sigma / sigma_max
+    fn hit(&self, ray: &Ray, ray_t_possible: &Interval) -> Option<HitRec> {
+        let mut rng = rand::rng();
+        let t = self.track(ray, ray_t_possible, &mut rng, |t, rng| {
+            rng.random::<f64>() * self.sigma_max < self.sigma(&ray.at(t))
+        })?;
+        // normal is arbitrary, phase functions don't use it
+        Some(HitRec::new(
+            ray.at(t),
+            Point::new(1.0, 0.0, 0.0),
+            t,
+            Arc::clone(&self.medium),
+        ))
+    }
+
+    // ratio tracking estimates transmittance T without stopping at collisions,
+    // the ray is then blocked with probability 1 - T, which keeps shadows unbiased
+    fn occluded(&self, ray: &Ray, ray_t_possible: &Interval) -> bool {
+        let mut rng = rand::rng();
+        let mut transmittance = 1.0;
+        self.track(ray, ray_t_possible, &mut rng, |t, _| {
+            transmittance *= 1.0 - self.sigma(&ray.at(t)) / self.sigma_max;
+            transmittance <= 0.0
+        });
+        rng.random::<f64>() >= transmittance
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        &self.bbox
+    }
+}
+
+#[test]
+fn test_density_grid_trilinear_lookup() {
+    let grid = DensityGrid::parse("# two voxels along x\n2 1 1\n0.0 1.0\n").expect("valid grid");
+    // voxel centers are at x = 0.25 and x = 0.75
+    assert_approx_eq::assert_approx_eq!(grid.lookup(&Point::new(0.25, 0.5, 0.5)), 0.0);
+    assert_approx_eq::assert_approx_eq!(grid.lookup(&Point::new(0.5, 0.5, 0.5)), 0.5);
+    assert_approx_eq::assert_approx_eq!(grid.lookup(&Point::new(1.0, 0.5, 0.5)), 1.0);
+    assert!(matches!(
+        DensityGrid::parse("2 2 2\n1.0"),
+        Err(GridError::SizeMismatch {
+            expected: 8,
+            found: 1
+        })
+    ));
+}
+
+#[test]
+fn test_density_grid_rejects_non_finite_values() {
+    for text in [
+        "2 1 1
+0.5 inf",
+        "2 1 1
+0.5 NaN",
+        "2 1 1
+
+-1.0 0.5",
+    ] {
+        assert!(
+            matches!(DensityGrid::parse(text), Err(GridError::Parse(l, _)) if l == 2 || l == 3),
+            "{text}"
+        );
+    }
+    assert!(matches!(
+        DensityGrid::new([2, 1, 1], vec![0.5, f64::INFINITY]),
+        Err(GridError::InvalidValue(1))
+    ));
+    assert!(matches!(
+        DensityGrid::new([1, 1, 1], vec![f64::NAN]),
+        Err(GridError::InvalidValue(0))
+    ));
+}
+
+#[test]
+fn test_density_grid_binary_matches_text() {
+    let mut bytes = BINARY_MAGIC.to_vec();
+    for word in [2_u32, 1, 1] {
+        bytes.extend(word.to_le_bytes());
+    }
+    for value in [0.0_f32, 1.0] {
+        bytes.extend(value.to_le_bytes());
+    }
+    let binary = DensityGrid::parse_bytes(&bytes).expect("valid binary grid");
+    let text = DensityGrid::parse("2 1 1\n0.0 1.0").expect("valid grid");
+    for x in [0.1, 0.4, 0.5, 0.9] {
+        let p = Point::new(x, 0.5, 0.5);
+        assert_approx_eq::assert_approx_eq!(binary.lookup(&p), text.lookup(&p));
+    }
+
+    bytes.pop();
+    assert!(matches!(
+        DensityGrid::parse_bytes(&bytes),
+        Err(GridError::SizeMismatch {
+            expected: 2,
+            found: 1
+        })
+    ));
+    assert!(matches!(
+        DensityGrid::parse_bytes(&bytes[..10]),
+        Err(GridError::Parse(0, _))
+    ));
+}
+
+#[cfg(test)]
+fn constant_volume(density: f64, albedo: ARgb, emission: Option<ARgb>) -> VoxelVolume {
+    use super::{
+        phase::{IsotropicPhase, PhaseMaterial},
+        texture::{SolidColor, Texture},
+    };
+
+    let grid = || Arc::new(DensityGrid::new([2, 2, 2], vec![1.0; 8]).expect("valid grid"));
+    let albedo: Arc<dyn Texture> = Arc::new(SolidColor::new(albedo));
+    VoxelVolume::new(
+        Aabb::from_points(&Point::new(-1.0, -1.0, -1.0), &Point::new(1.0, 1.0, 1.0)),
+        grid(),
+        density,
+        Arc::new(PhaseMaterial::new(&albedo, Arc::new(IsotropicPhase))),
+        emission.map(|color| (grid(), color)),
+    )
+}
+
+#[test]
+fn test_voxel_volume_tracks_constant_density_exponentially() {
+    let volume = constant_volume(0.7, ARgb::new(1.0, 1.0, 1.0), None);
+    // straight through two units, and through one diagonal unit from the middle
+    let cases = [
+        (Point::new(0.2, -0.3, -4.0), Point::new(0.0, 0.0, 1.0), 2.0),
+        (Point::default(), Point::new(1.0, 1.0, 0.0), f64::sqrt(2.0)),
+    ];
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let n: u32 = 20_000;
+    for (orig, dir, inside) in cases {
+        let ray = Ray::new(orig, dir, None);
+        let expected = f64::exp(-0.7 * inside);
+        // delta tracking for scattering, ratio tracking for shadows
+        let passed = (0..n)
+            .filter(|_| volume.hit(&ray, &ray_t).is_none())
+            .count();
+        let unblocked = (0..n).filter(|_| !volume.occluded(&ray, &ray_t)).count();
+        for count in [passed, unblocked] {
+            #[allow(clippy::cast_precision_loss)]
+            let p = count as f64 / f64::from(n);
+            assert!((p - expected).abs() < 0.015, "{p} vs {expected}");
+        }
+    }
+}
+
+#[test]
+fn test_voxel_emission_glows_by_optical_depth() {
+    let glow = ARgb::new(2.0, 1.0, 0.5);
+    let ray = Ray::new(Point::new(0.0, 0.0, -4.0), Point::new(0.0, 0.0, 1.0), None);
+    let ray_t = Interval::new(0.001, f64::INFINITY);
+    let n: u32 = 20_000;
+    // thin and thick absorbing volumes, two units across
+    for density in [0.2, 5.0] {
+        let volume = constant_volume(density, ARgb::default(), Some(glow));
+        let mut seen = ARgb::default();
+        for _ in 0..n {
+            if let Some(hr) = volume.hit(&ray, &ray_t) {
+                seen = seen + hr.mat.emitted(0.0, 0.0, &hr.p);
+            }
+        }
+        let seen = seen / f64::from(n);
+        let expected = glow * (1.0 - f64::exp(-2.0 * density));
+        for (got, want) in seen.channels().iter().zip(expected.channels()) {
+            assert!((got - want).abs() < 0.03 * want, "{seen:?} vs {expected:?}");
+        }
+    }
+}
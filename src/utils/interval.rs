@@ -15,10 +15,12 @@ pub const UNIVERSE: Interval = Interval {
 };
 
 impl Interval {
+    #[must_use]
     pub fn new(min: f64, max: f64) -> Self {
         Interval { min, max }
     }
 
+    #[must_use]
     pub fn enclosing(lhs: &Interval, rhs: &Interval) -> Self {
         Interval {
             min: if lhs.min <= rhs.min { lhs.min } else { rhs.min },
@@ -26,18 +28,22 @@ impl Interval {
         }
     }
 
+    #[must_use]
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
 
+    #[must_use]
     pub fn contains(&self, val: f64) -> bool {
         self.min <= val && self.max >= val
     }
 
+    #[must_use]
     pub fn surrounds(&self, val: f64) -> bool {
         self.min < val && self.max > val
     }
 
+    #[must_use]
     pub fn expand(&self, delta: f64) -> Self {
         Interval::new(self.min - delta, self.max + delta)
     }
@@ -2,6 +2,7 @@
 
 use rand::seq::IndexedRandom;
 
+#[must_use]
 pub fn f64_to_u32(value: f64) -> Option<u32> {
     if value.is_nan() || value < 0.0 || value > f64::from(u32::MAX) {
         None
@@ -10,6 +11,7 @@ pub fn f64_to_u32(value: f64) -> Option<u32> {
     }
 }
 
+#[must_use]
 pub fn safe_f64_to_u8_clamp(value: f64) -> Option<u8> {
     if value.is_nan() {
         None
@@ -30,6 +32,8 @@ pub enum Axis {
 pub const AXES: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
 
 impl Axis {
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
     pub fn rand() -> Axis {
         *AXES
             .choose(&mut rand::rng())